@@ -0,0 +1,209 @@
+use crate::color::ColorDepth;
+use anyhow::Result;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use reqwest::Client;
+use std::time::Duration;
+
+/// Downloads and decodes the cover at `url`.
+pub async fn fetch(client: &Client, url: &str) -> Result<DynamicImage> {
+    let bytes = client
+        .get(url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    // Decoding is CPU-bound; keep it off the async workers.
+    let image = tokio::task::spawn_blocking(move || image::load_from_memory(&bytes)).await??;
+    Ok(image)
+}
+
+/// Draws `image` as large as fits in `width` x `height` cells, centred. Each
+/// cell is a `▀` showing two pixels: the top one as foreground, the bottom as
+/// background, in the nearest colors `depth` has.
+pub fn render(
+    image: &DynamicImage,
+    width: u16,
+    height: u16,
+    depth: ColorDepth,
+) -> Vec<Line<'static>> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let pixels = image
+        .resize(width as u32, height as u32 * 2, FilterType::Triangle)
+        .to_rgb8();
+    let rows = pixels.height().div_ceil(2);
+    let pad_x = (width as u32 - pixels.width()) / 2;
+    let pad_y = (height as u32 - rows) / 2;
+
+    let mut lines = vec![Line::from(""); pad_y as usize];
+    for row in 0..rows {
+        let mut spans = vec![Span::raw(" ".repeat(pad_x as usize))];
+        for x in 0..pixels.width() {
+            let top = pixels.get_pixel(x, row * 2);
+            let style = Style::default().fg(depth.adapt(Color::Rgb(top[0], top[1], top[2])));
+            let style = match pixels.get_pixel_checked(x, row * 2 + 1) {
+                Some(bottom) => style.bg(depth.adapt(Color::Rgb(bottom[0], bottom[1], bottom[2]))),
+                None => style,
+            };
+            spans.push(Span::styled("▀", style));
+        }
+        lines.push(Line::from(spans));
+    }
+    lines
+}
+
+/// `text` as lines, with the colors and bold/dim/italic/underline set by any
+/// ANSI escapes in it (the named colors, the 256-color palette and
+/// `38;2;r;g;b`) brought down to what `depth` has. Other escapes are dropped.
+pub fn ansi_lines(text: &str, depth: ColorDepth) -> Vec<Line<'static>> {
+    let mut style = Style::default();
+    text.lines()
+        .map(|line| {
+            let mut spans = Vec::new();
+            let mut rest = line;
+            while let Some(start) = rest.find('\x1b') {
+                if start > 0 {
+                    spans.push(Span::styled(rest[..start].to_string(), style));
+                }
+                rest = &rest[start + 1..];
+                let Some(params) = rest.strip_prefix('[') else {
+                    continue;
+                };
+                // parameters and intermediates, then the letter that ends it
+                let end = params
+                    .find(|c: char| ('\x40'..='\x7e').contains(&c))
+                    .unwrap_or(params.len());
+                if params[end..].starts_with('m') {
+                    style = apply_sgr(style, &params[..end], depth);
+                }
+                rest = params.get(end + 1..).unwrap_or("");
+            }
+            if !rest.is_empty() {
+                spans.push(Span::styled(rest.to_string(), style));
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
+
+fn apply_sgr(mut style: Style, params: &str, depth: ColorDepth) -> Style {
+    let codes: Vec<u8> = params
+        .split(';')
+        .map(|code| code.parse().unwrap_or(0))
+        .collect();
+    let mut codes = codes.iter().copied();
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            30..=37 => style.fg(named(Color::Indexed(code - 30))),
+            90..=97 => style.fg(named(Color::Indexed(code - 90 + 8))),
+            40..=47 => style.bg(named(Color::Indexed(code - 40))),
+            100..=107 => style.bg(named(Color::Indexed(code - 100 + 8))),
+            39 => style.fg(Color::Reset),
+            49 => style.bg(Color::Reset),
+            38 | 48 => {
+                let color = match codes.next() {
+                    Some(5) => codes.next().map(Color::Indexed),
+                    Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                        (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match (code, color.map(|color| depth.adapt(named(color)))) {
+                    (38, Some(color)) => style.fg(color),
+                    (_, Some(color)) => style.bg(color),
+                    (_, None) => style,
+                }
+            }
+            _ => style,
+        };
+    }
+    style
+}
+
+/// The first 16 palette entries as the named colors they are, so they're
+/// left to the terminal's own scheme.
+fn named(color: Color) -> Color {
+    const NAMED: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+    match color {
+        Color::Indexed(i) if i < 16 => NAMED[i as usize],
+        color => color,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansi_escapes_become_styles() {
+        let text = "\x1b[1;35mpla\x1b[38;2;0;255;255mza\x1b[0m!\n\x1b[2K\x1b[38;5;196mred";
+        let lines = ansi_lines(text, ColorDepth::TrueColor);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0].spans,
+            vec![
+                Span::styled(
+                    "pla",
+                    Style::default()
+                        .fg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD)
+                ),
+                Span::styled(
+                    "za",
+                    Style::default()
+                        .fg(Color::Rgb(0, 255, 255))
+                        .add_modifier(Modifier::BOLD)
+                ),
+                Span::raw("!"),
+            ]
+        );
+        // the line clear is dropped
+        assert_eq!(
+            lines[1].spans,
+            vec![Span::styled(
+                "red",
+                Style::default().fg(Color::Indexed(196))
+            )]
+        );
+        assert_eq!(
+            ansi_lines("\x1b[38;5;196mred", ColorDepth::Ansi16)[0].spans[0]
+                .style
+                .fg,
+            Some(Color::LightRed)
+        );
+    }
+}
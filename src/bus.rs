@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+/// Interleaved samples exactly as the decoder produced them.
+#[derive(Clone)]
+pub struct Chunk {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Arc<[i16]>,
+}
+
+/// Hands every decoded chunk to any number of extra consumers (recorders and
+/// the like) next to the live sink. It outlives reconnects, so a subscriber
+/// keeps receiving audio across decoder restarts.
+#[derive(Default)]
+pub struct SampleBus {
+    taps: Mutex<Vec<mpsc::Sender<Chunk>>>,
+}
+
+impl SampleBus {
+    pub fn subscribe(&self) -> mpsc::Receiver<Chunk> {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut taps) = self.taps.lock() {
+            taps.push(tx);
+        }
+        rx
+    }
+
+    /// Taps are unbounded so a slow consumer never holds up playback.
+    fn publish(&self, chunk: &Chunk) {
+        if let Ok(mut taps) = self.taps.lock() {
+            taps.retain(|tap| tap.send(chunk.clone()).is_ok());
+        }
+    }
+
+    /// Disconnects every subscriber so it can finish up.
+    pub fn close(&self) {
+        if let Ok(mut taps) = self.taps.lock() {
+            taps.clear();
+        }
+    }
+}
+
+/// The live source's end of a `Feed`.
+pub struct Incoming {
+    pub chunks: mpsc::Receiver<Chunk>,
+    /// Raised once the decoder has nothing more to send, so the source can
+    /// finish as soon as it has played what's queued instead of waiting for
+    /// the channel to close.
+    pub ended: Arc<AtomicBool>,
+}
+
+impl Incoming {
+    pub fn ended(&self) -> bool {
+        self.ended.load(Ordering::SeqCst)
+    }
+}
+
+/// Where one decoder run sends its audio: the source it queued on the sink,
+/// plus whoever is subscribed to the bus.
+pub struct Feed {
+    live: mpsc::SyncSender<Chunk>,
+    ended: Arc<AtomicBool>,
+    bus: Arc<SampleBus>,
+    channels: u16,
+    sample_rate: u32,
+    // the live source gets one channel; the bus still gets them all
+    mono: bool,
+}
+
+impl Feed {
+    pub fn new(
+        live: mpsc::SyncSender<Chunk>,
+        ended: Arc<AtomicBool>,
+        bus: Arc<SampleBus>,
+        channels: u16,
+        sample_rate: u32,
+        mono: bool,
+    ) -> Self {
+        Self {
+            live,
+            ended,
+            bus,
+            channels,
+            sample_rate,
+            mono,
+        }
+    }
+
+    /// Keeps the live source going without publishing, for a decoder that's
+    /// on its way out while the next one takes over the bus.
+    pub fn live_only(self) -> Self {
+        Self {
+            bus: Arc::default(),
+            ..self
+        }
+    }
+
+    /// Tells the live source the stream has ended or failed; it stops once
+    /// it has played what it has.
+    pub fn end(&self) {
+        self.ended.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns false once the live source has gone away and decoding should stop.
+    pub fn send(&self, samples: &[i16]) -> bool {
+        let chunk = Chunk {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            samples: samples.into(),
+        };
+        self.bus.publish(&chunk);
+        let chunk = if self.mono && self.channels > 1 {
+            Chunk {
+                channels: 1,
+                sample_rate: self.sample_rate,
+                samples: downmix(&chunk.samples, self.channels).into(),
+            }
+        } else {
+            chunk
+        };
+        self.live.send(chunk).is_ok()
+    }
+}
+
+/// Averages each frame of interleaved samples into one.
+fn downmix(samples: &[i16], channels: u16) -> Vec<i16> {
+    samples
+        .chunks(channels as usize)
+        .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downmix_averages_frames() {
+        assert_eq!(
+            downmix(&[100, 300, -32768, -32768, 32767, -32768], 2),
+            [200, -32768, 0]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::config::{Backend, ColorChoice, LayoutMode, Visualizer};
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Plaza Radio in your terminal")]
+pub struct Cli {
+    /// Config file to use instead of the one in the config directory
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Stream URL to play, overriding the config file
+    #[arg(long, value_name = "URL")]
+    pub stream: Option<String>,
+    /// Audio output to play through: any part of its name
+    #[arg(long, value_name = "NAME")]
+    pub device: Option<String>,
+    /// Print the names of the audio outputs, then exit
+    #[arg(long)]
+    pub list_devices: bool,
+    /// Volume to start at, in percent
+    #[arg(long, value_name = "0-200", value_parser = clap::value_parser!(u16).range(0..=200))]
+    pub volume: Option<u16>,
+    /// Audio decoding backend, overriding the config file
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+    /// Visualizer to start with
+    #[arg(long, value_enum)]
+    pub visualizer: Option<Visualizer>,
+    /// How many colors the terminal can show, overriding the config file
+    #[arg(long, value_enum)]
+    pub color: Option<ColorChoice>,
+    /// Layout to start with
+    #[arg(long, value_enum)]
+    pub layout: Option<LayoutMode>,
+    /// Start in the compact layout, the same as `--layout compact`
+    #[arg(long, conflicts_with = "layout")]
+    pub compact: bool,
+    /// Log in to Last.fm for scrobbling, then exit
+    #[arg(long)]
+    pub lastfm_auth: bool,
+    /// Print the tracks liked with `f`, then exit
+    #[arg(long)]
+    pub list_favorites: bool,
+    /// Follow the metadata without playing anything
+    #[arg(long)]
+    pub no_audio: bool,
+    /// With --no-audio, print each new track to stdout instead of showing the TUI
+    #[arg(long, requires = "no_audio")]
+    pub print: bool,
+    /// Print one line about what the running player is playing, through its
+    /// `ipc_socket`, then exit; with nothing to ask, print nothing and fail
+    #[arg(long)]
+    pub status: bool,
+    /// The line --status prints; {artist}, {title}, {track}, {state} and
+    /// {volume} are filled in
+    #[arg(long, value_name = "TEMPLATE", requires = "status")]
+    pub format: Option<String>,
+    /// Send a command to the running player through its `ipc_socket`, then
+    /// exit: play, pause, toggle, stop, "vol 0.7" or "vol +0.05"
+    #[arg(long, value_name = "COMMAND", conflicts_with = "status")]
+    pub send: Option<String>,
+    /// Ask the metadata API once what's playing, print it and exit, without
+    /// the player or the TUI; fails if nothing is
+    #[arg(long, conflicts_with_all = ["status", "send", "no_audio"])]
+    pub print_now_playing: bool,
+    /// With --print-now-playing, print the track as JSON
+    #[arg(long, requires = "print_now_playing", conflicts_with = "plain")]
+    pub json: bool,
+    /// With --print-now-playing, print "artist - title" (the default)
+    #[arg(long, requires = "print_now_playing")]
+    pub plain: bool,
+    /// Append a log of what the player is doing to this file
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Include debug detail in the log, such as ffmpeg's own output
+    #[arg(short, long, requires = "log_file")]
+    pub verbose: bool,
+}
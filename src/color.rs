@@ -0,0 +1,170 @@
+use crate::config::ColorChoice;
+use ratatui::style::Color;
+
+/// How many colors the terminal can show. Colors given as RGB are brought
+/// down to the nearest it has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    /// The xterm palette: the 16 named colors, a 6x6x6 cube and a grey ramp.
+    Ansi256,
+    /// Just the named colors.
+    Ansi16,
+}
+
+// What the 16 named colors usually look like (xterm's defaults), to measure
+// against.
+const NAMED: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// The levels each channel of the 256-color cube steps through.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    /// What `choice` comes to, asking the environment for `auto`.
+    pub fn new(choice: ColorChoice) -> Self {
+        match choice {
+            ColorChoice::Auto => Self::detect(
+                std::env::var("COLORTERM").ok().as_deref(),
+                std::env::var("TERM").ok().as_deref(),
+            ),
+            ColorChoice::Truecolor => ColorDepth::TrueColor,
+            ColorChoice::Colors256 => ColorDepth::Ansi256,
+            ColorChoice::Colors16 => ColorDepth::Ansi16,
+        }
+    }
+
+    /// Truecolor terminals say so in `COLORTERM`; `TERM` names like
+    /// "xterm-256color" give away the 256-color ones. Anything else gets the
+    /// 16 every terminal has.
+    fn detect(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            ColorDepth::TrueColor
+        } else if term.is_some_and(|term| term.contains("256color")) {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// `color`, or the nearest the terminal can show.
+    pub fn adapt(self, color: Color) -> Color {
+        let rgb = match (self, color) {
+            (ColorDepth::TrueColor, _) => return color,
+            (_, Color::Rgb(r, g, b)) => (r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(i)) if i >= 16 => indexed_rgb(i),
+            _ => return color,
+        };
+        match self {
+            ColorDepth::Ansi256 => Color::Indexed(nearest_indexed(rgb)),
+            _ => nearest_named(rgb),
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn nearest_named(rgb: (u8, u8, u8)) -> Color {
+    NAMED
+        .iter()
+        .min_by_key(|(_, named)| distance(rgb, *named))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+/// The closest of the cube and the grey ramp; the 16 named colors at the
+/// start of the palette vary too much between terminals to aim for.
+fn nearest_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let level = |c: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| (CUBE[i] as i32 - c as i32).abs())
+            .unwrap_or(0) as u8
+    };
+    let cube = 16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2);
+
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let grey = 232 + ((average.saturating_sub(3) / 10).min(23)) as u8;
+
+    if distance(rgb, indexed_rgb(grey)) < distance(rgb, indexed_rgb(cube)) {
+        grey
+    } else {
+        cube
+    }
+}
+
+/// What palette entry `i` (16 and up) looks like.
+fn indexed_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => NAMED[i as usize].1,
+        16..=231 => {
+            let i = i - 16;
+            (
+                CUBE[(i / 36) as usize],
+                CUBE[(i / 6 % 6) as usize],
+                CUBE[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (i - 232);
+            (level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_comes_from_the_environment() {
+        let detect = ColorDepth::detect;
+        assert_eq!(
+            detect(Some("truecolor"), Some("xterm")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(detect(None, Some("xterm-256color")), ColorDepth::Ansi256);
+        assert_eq!(
+            detect(Some("yes"), Some("screen-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(detect(None, Some("linux")), ColorDepth::Ansi16);
+        assert_eq!(detect(None, None), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn rgb_is_brought_down_to_the_nearest() {
+        let orange = Color::Rgb(0xff, 0x8c, 0x61);
+        assert_eq!(ColorDepth::TrueColor.adapt(orange), orange);
+        // 255, 135, 95 in the cube
+        assert_eq!(ColorDepth::Ansi256.adapt(orange), Color::Indexed(209));
+        assert_eq!(
+            ColorDepth::Ansi256.adapt(Color::Rgb(0x80, 0x80, 0x80)),
+            Color::Indexed(244)
+        );
+        assert_eq!(ColorDepth::Ansi16.adapt(orange), Color::Yellow);
+        assert_eq!(
+            ColorDepth::Ansi16.adapt(Color::Indexed(51)),
+            Color::LightCyan
+        );
+        // named colors are left to the terminal
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Magenta), Color::Magenta);
+    }
+}
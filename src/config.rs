@@ -0,0 +1,623 @@
+use crate::keymap::{Action, Keys};
+use crate::search;
+use crate::theme::{ThemeColor, ThemePreset};
+use anyhow::{bail, Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+/// Written to the config path on first run so there's something to edit.
+const EXAMPLE: &str = include_str!("../config.example.toml");
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Volume at startup, from 0.0 to 2.0.
+    pub default_volume: f32,
+    /// Start with the sound off, as the last run was left; not read from the
+    /// file.
+    #[serde(skip)]
+    pub start_muted: bool,
+    /// The loudest the volume can be turned up to, at most 2.0 (200%).
+    pub max_volume: f32,
+    /// Play through the first audio output whose name contains this,
+    /// instead of the default one.
+    pub output_device: Option<String>,
+    /// Play this instead of picking one of the built-in streams.
+    pub stream_url: Option<String>,
+    /// Built-in stream to try first ("mp3", "ogg" or "opus").
+    pub preferred_format: Option<String>,
+    /// Seconds between metadata polls when the track's length isn't known.
+    pub metadata_interval_secs: u64,
+    /// Relative cover URLs are resolved against this instead of the address
+    /// the metadata came from.
+    pub art_base_url: Option<String>,
+    /// Every request, the audio included, goes through this proxy. Without it
+    /// HTTP_PROXY, HTTPS_PROXY and ALL_PROXY are used if they're set.
+    pub proxy: Option<String>,
+    /// Sent as the User-Agent of every request, the audio's included.
+    pub user_agent: Option<String>,
+    /// Extra headers for every request, e.g. an Authorization for a private relay.
+    pub headers: HashMap<String, String>,
+    /// Addresses to use for these hosts instead of looking them up.
+    pub resolve: HashMap<String, IpAddr>,
+    /// How many times in a row the decoder may be restarted without producing audio
+    /// before the player gives up and asks the user what to do.
+    pub max_reconnect_attempts: u32,
+    /// Longest wait between attempts to pick a dropped download back up
+    /// before the decoder is restarted.
+    pub reconnect_delay_max_secs: u32,
+    /// How long to wait on a silent connection before giving up on it.
+    pub stall_timeout_secs: u32,
+    /// How long the stream can play nothing but silence before the player
+    /// pauses and disconnects; 0 never does.
+    pub idle_timeout_secs: u32,
+    /// Fraction of the current volume to drop to while ducked.
+    pub duck_level: f32,
+    pub duck_ramp_ms: u64,
+    pub volume_style: VolumeStyle,
+    /// The time shown on the info panel's bottom edge.
+    pub clock: ClockFormat,
+    /// Show how long the player has been running next to the clock.
+    pub session_timer: bool,
+    /// What happens when a new track starts.
+    pub track_alert: TrackAlert,
+    /// Tracks heard for less than this aren't added to the session history
+    /// or scrobbled.
+    pub min_listen_secs: u64,
+    pub backend: Backend,
+    /// Read combined "A - B" stream titles as "Title - Artist".
+    pub title_first: bool,
+    pub layout: LayoutMode,
+    /// A text file to show instead of the Plaza logo while there's no cover.
+    pub logo_file: Option<PathBuf>,
+    pub visualizer: Visualizer,
+    /// Show the controls as a single footer line instead of a list.
+    pub compact_controls: bool,
+    /// How long a new track must stay current before history and artwork follow it.
+    pub track_settle_ms: u64,
+    /// Fade the audio out over this long when quitting; 0 stops immediately.
+    pub quit_fade_ms: u64,
+    /// Fade in at startup and around pause/resume over this long; 0 turns it off.
+    pub fade_ms: u64,
+    /// Switching stations fades the old one out under the new over this long; 0 cuts straight over.
+    pub crossfade_ms: u64,
+    /// Even out loudness between tracks (g toggles it).
+    pub normalize: bool,
+    /// Play both channels mixed into one, for a single speaker (M toggles it).
+    pub mono: bool,
+    /// Level normalization aims for, in dB RMS; roughly LUFS.
+    pub normalize_target_db: f32,
+    /// How long fields that changed on a metadata update stay highlighted.
+    pub highlight_ms: u64,
+    /// Disconnect from the stream while paused instead of buffering in the
+    /// background; resuming reconnects to the live stream.
+    pub pause_disconnects: bool,
+    /// Audio to collect before playback starts.
+    pub prebuffer_ms: u64,
+    /// The most audio held ahead of playback. Past this the oldest is dropped,
+    /// so playback never drifts far behind the stream; 0 for no limit.
+    pub max_buffer_ms: u64,
+    /// Write the session's history here when quitting.
+    pub history_export: Option<PathBuf>,
+    pub history_format: HistoryFormat,
+    /// Also write everything that's played to this WAV file.
+    pub record_to: Option<PathBuf>,
+    /// Where recordings started with r go; your music folder by default.
+    pub record_dir: Option<PathBuf>,
+    /// Unix socket to send the status to scripts on, and take commands from.
+    pub ipc_socket: Option<PathBuf>,
+    /// Where o searches for the track; `{query}` is replaced by its artist and title.
+    pub search_url: String,
+    /// Keys to use instead of the defaults, by action.
+    pub keybindings: HashMap<Action, Keys>,
+    /// Other streams to switch to, after Plaza's own.
+    pub stations: Vec<Station>,
+    /// How many colors the terminal has, for bringing covers and the theme
+    /// down to what it can show.
+    pub color: ColorChoice,
+    pub theme: ThemeConfig,
+    pub eq: EqConfig,
+    pub lastfm: Option<LastFm>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Station {
+    pub name: String,
+    pub url: String,
+    /// JSON now-playing endpoint. Without one, only titles sent along with
+    /// the audio are shown.
+    pub metadata_url: Option<String>,
+}
+
+/// A built-in palette, with any of its colors replaced (see `Theme`).
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub preset: ThemePreset,
+    pub border: Option<ThemeColor>,
+    pub art_border: Option<ThemeColor>,
+    pub title: Option<ThemeColor>,
+    pub label: Option<ThemeColor>,
+    pub status_playing: Option<ThemeColor>,
+    pub status_paused: Option<ThemeColor>,
+    pub volume_label: Option<ThemeColor>,
+    pub waveform: Option<ThemeColor>,
+    pub art_gradient_start: Option<ThemeColor>,
+    pub art_gradient_end: Option<ThemeColor>,
+}
+
+/// The equalizer (e opens it).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct EqConfig {
+    /// Preset to start with: "flat", "bass boost", "vocal" or one of `presets`.
+    pub preset: String,
+    /// Your own presets, as gains in dB for 60 Hz, 250 Hz, 1 kHz, 4 kHz and 12 kHz.
+    pub presets: HashMap<String, [f32; 5]>,
+}
+
+impl Default for EqConfig {
+    fn default() -> Self {
+        Self {
+            preset: "flat".to_string(),
+            presets: HashMap::new(),
+        }
+    }
+}
+
+/// Scrobbling to Last.fm, with an API account from last.fm/api/account/create.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LastFm {
+    pub api_key: String,
+    pub api_secret: String,
+    /// Filled in by `--lastfm-auth`.
+    pub session_key: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayoutMode {
+    /// Artwork on the left, track info on the right.
+    #[default]
+    Split,
+    /// Just the artwork with a one-line caption, for small displays.
+    ArtOnly,
+    /// A few lines of text: the track, a thin volume bar and the keys, for
+    /// tiny panes.
+    Compact,
+}
+
+/// What's drawn under the volume in the info panel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Visualizer {
+    Off,
+    /// Frequency bars computed from what's playing.
+    #[default]
+    #[serde(alias = "spectrum")]
+    #[value(alias = "spectrum")]
+    Waveform,
+    /// Left and right level meters.
+    Vu,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// ffmpeg if it can be started, symphonia otherwise.
+    #[default]
+    Auto,
+    /// Decode with an external `ffmpeg` process.
+    Ffmpeg,
+    /// Decode in-process; supports mp3, ogg/vorbis and flac streams.
+    Symphonia,
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Auto => "auto",
+            Backend::Ffmpeg => "ffmpeg",
+            Backend::Symphonia => "symphonia",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeStyle {
+    #[default]
+    Horizontal,
+    /// A slider along the right edge of the info panel.
+    Vertical,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum ClockFormat {
+    #[serde(rename = "off")]
+    Off,
+    #[default]
+    #[serde(rename = "24h")]
+    TwentyFour,
+    /// With AM/PM.
+    #[serde(rename = "12h")]
+    Twelve,
+}
+
+const DEFAULT_USER_AGENT: &str = concat!("plaza_term_rs/", env!("CARGO_PKG_VERSION"));
+
+// Where reqwest looks for a proxy when there's none in the config.
+const PROXY_VARS: [&str; 6] = [
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+/// What every request goes out with, the metadata's and the audio's alike.
+#[derive(Clone)]
+pub struct Http {
+    pub user_agent: HeaderValue,
+    pub headers: HeaderMap,
+    /// Without one, the environment's (HTTP_PROXY and so on) is used.
+    pub proxy: Option<reqwest::Proxy>,
+    /// Hosts that aren't looked up. The port's ignored; the URL's is used.
+    pub resolve: Vec<(String, SocketAddr)>,
+}
+
+impl Http {
+    pub fn client(&self) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(self.user_agent.clone())
+            .default_headers(self.headers.clone());
+        for (host, addr) in &self.resolve {
+            builder = builder.resolve(host, *addr);
+        }
+        match &self.proxy {
+            Some(proxy) => builder.proxy(proxy.clone()),
+            None => builder,
+        }
+    }
+
+    pub fn blocking_client(&self) -> reqwest::blocking::ClientBuilder {
+        let mut builder = reqwest::blocking::Client::builder()
+            .user_agent(self.user_agent.clone())
+            .default_headers(self.headers.clone());
+        for (host, addr) in &self.resolve {
+            builder = builder.resolve(host, *addr);
+        }
+        match &self.proxy {
+            Some(proxy) => builder.proxy(proxy.clone()),
+            None => builder,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Truecolor if COLORTERM says so, 256 colors if TERM does, 16 otherwise.
+    #[default]
+    Auto,
+    Truecolor,
+    #[serde(rename = "256")]
+    #[value(name = "256")]
+    Colors256,
+    #[serde(rename = "16")]
+    #[value(name = "16")]
+    Colors16,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackAlert {
+    #[default]
+    Off,
+    /// Ring the terminal bell.
+    Bell,
+    /// Flash the borders.
+    Flash,
+    Both,
+}
+
+impl TrackAlert {
+    pub fn bell(self) -> bool {
+        matches!(self, TrackAlert::Bell | TrackAlert::Both)
+    }
+
+    pub fn flash(self) -> bool {
+        matches!(self, TrackAlert::Flash | TrackAlert::Both)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_volume: 0.5,
+            start_muted: false,
+            max_volume: 2.0,
+            output_device: None,
+            stream_url: None,
+            preferred_format: None,
+            metadata_interval_secs: 5,
+            art_base_url: None,
+            proxy: None,
+            user_agent: None,
+            headers: HashMap::new(),
+            resolve: HashMap::new(),
+            max_reconnect_attempts: 5,
+            reconnect_delay_max_secs: 5,
+            stall_timeout_secs: 15,
+            idle_timeout_secs: 60,
+            duck_level: 0.2,
+            duck_ramp_ms: 400,
+            volume_style: VolumeStyle::default(),
+            clock: ClockFormat::default(),
+            session_timer: false,
+            track_alert: TrackAlert::default(),
+            min_listen_secs: 30,
+            backend: Backend::default(),
+            title_first: false,
+            layout: LayoutMode::default(),
+            logo_file: None,
+            visualizer: Visualizer::default(),
+            compact_controls: false,
+            track_settle_ms: 2000,
+            quit_fade_ms: 0,
+            fade_ms: 500,
+            crossfade_ms: 2000,
+            normalize: false,
+            normalize_target_db: -18.0,
+            mono: false,
+            highlight_ms: 1500,
+            pause_disconnects: false,
+            prebuffer_ms: 1000,
+            max_buffer_ms: 5000,
+            history_export: None,
+            history_format: HistoryFormat::default(),
+            record_to: None,
+            record_dir: None,
+            ipc_socket: None,
+            search_url: search::DEFAULT_URL.to_string(),
+            keybindings: HashMap::new(),
+            stations: Vec::new(),
+            color: ColorChoice::default(),
+            theme: ThemeConfig::default(),
+            eq: EqConfig::default(),
+            lastfm: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|d| d.join("plaza_radio_tui").join("config.toml"))
+    }
+
+    /// Loads the config file, falling back to defaults when it doesn't exist
+    /// (and leaving a commented example there for next time).
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        if !path.exists() {
+            // Not being able to write the example shouldn't stop playback.
+            if let Some(dir) = path.parent() {
+                let _ = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, EXAMPLE));
+            }
+            return Ok(Self::default());
+        }
+        Self::load_from(&path)
+    }
+
+    /// `user_agent`, `headers`, `proxy` and `resolve`, checked and ready to
+    /// build clients with. Credentials in the proxy's URL are sent as Basic
+    /// auth.
+    pub fn http(&self) -> Result<Http> {
+        self.check_proxy(|var| std::env::var(var).ok())?;
+        let user_agent = match &self.user_agent {
+            Some(agent) => HeaderValue::from_str(agent)
+                .with_context(|| format!("Invalid user_agent {:?}", agent))?,
+            None => HeaderValue::from_static(DEFAULT_USER_AGENT),
+        };
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name {:?} in [headers]", name))?;
+            // the value may well be a secret, so it's left out of the error
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for the {} header", name))?;
+            headers.insert(name, value);
+        }
+        let proxy = self
+            .proxy
+            .as_deref()
+            .map(|url| reqwest::Proxy::all(url).with_context(|| format!("Invalid proxy {:?}", url)))
+            .transpose()?;
+        Ok(Http {
+            user_agent,
+            headers,
+            proxy,
+            resolve: self
+                .resolve
+                .iter()
+                .map(|(host, ip)| (host.clone(), SocketAddr::new(*ip, 0)))
+                .collect(),
+        })
+    }
+
+    /// Refuses what can't be honored rather than connecting some other way:
+    /// a SOCKS proxy, which this build can't use (and reqwest would skip
+    /// when it's in the environment, going out directly), and `[resolve]`
+    /// together with a proxy, which does its own lookups. `env` reads an
+    /// environment variable.
+    fn check_proxy(&self, env: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let proxies: Vec<(&str, String)> = match &self.proxy {
+            Some(url) => vec![("proxy", url.clone())],
+            None => PROXY_VARS
+                .iter()
+                .filter_map(|&var| Some((var, env(var).filter(|url| !url.is_empty())?)))
+                .collect(),
+        };
+        for (from, url) in &proxies {
+            if !url.trim().to_ascii_lowercase().starts_with("socks") {
+                continue;
+            }
+            if self.proxy.is_some() {
+                bail!("proxy is a SOCKS proxy, which plaza_tui can't use; go through an HTTP proxy instead (Tor has one: HTTPTunnelPort)");
+            }
+            bail!(
+                "{} is a SOCKS proxy, which plaza_tui can't use, and it won't connect around it; set proxy to an HTTP one in the config (Tor has one: HTTPTunnelPort), or run with {} unset to connect directly",
+                from,
+                from
+            );
+        }
+        match proxies.first() {
+            Some((from, _)) if !self.resolve.is_empty() => bail!(
+                "[resolve] can't be used with a proxy ({} is set), which looks hosts up itself",
+                from
+            ),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Loads the config file at `path`, which has to exist.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config in {}", path.display()))
+    }
+
+    /// Writes a Last.fm session key into the `[lastfm]` table of the config
+    /// file at `path`, leaving everything else (comments included) as it was.
+    pub fn save_lastfm_session(path: &Path, key: &str) -> Result<()> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        // only the old key under [lastfm] goes; another table may have its own
+        let mut table = "";
+        let mut lines: Vec<&str> = text
+            .lines()
+            .filter(|line| {
+                let line = line.trim();
+                if line.starts_with('[') {
+                    table = line;
+                }
+                let key = line.split('=').next().unwrap_or_default().trim();
+                !(table == "[lastfm]" && key == "session_key")
+            })
+            .collect();
+        let entry = format!("session_key = {:?}", key);
+        match lines.iter().position(|line| line.trim() == "[lastfm]") {
+            Some(i) => lines.insert(i + 1, &entry),
+            None => lines.extend(["", "[lastfm]", &entry]),
+        }
+        let text = lines.join("\n") + "\n";
+
+        toml::from_str::<Config>(&text).with_context(|| {
+            format!(
+                "Could not add the session key to {}; add session_key = {:?} under [lastfm] yourself",
+                path.display(),
+                key
+            )
+        })?;
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_are_checked() {
+        let config: Config = toml::from_str(
+            r#"
+            user_agent = "Mozilla/5.0"
+            [headers]
+            Authorization = "Bearer abc"
+            X-Relay = "plaza"
+            "#,
+        )
+        .unwrap();
+        let http = config.http().unwrap();
+        assert_eq!(http.user_agent, "Mozilla/5.0");
+        assert_eq!(http.headers["authorization"], "Bearer abc");
+        assert_eq!(http.headers["x-relay"], "plaza");
+
+        let bad_name: Config = toml::from_str("[headers]\n\"X Relay\" = \"plaza\"").unwrap();
+        assert!(bad_name.http().is_err());
+        let bad_value: Config = toml::from_str("[headers]\nX-Relay = \"a\\nb\"").unwrap();
+        let err = bad_value.http().err().unwrap().to_string();
+        assert!(err.contains("x-relay"), "{}", err);
+        assert!(!err.contains("a\nb"), "{}", err);
+    }
+
+    #[test]
+    fn proxies_that_cant_be_honored_are_refused() {
+        let no_env = |_: &str| None;
+        let socks: Config = toml::from_str(r#"proxy = "socks5h://127.0.0.1:9050""#).unwrap();
+        assert!(socks.check_proxy(no_env).is_err());
+
+        let resolve: Config =
+            toml::from_str("[resolve]\n\"radio.plaza.one\" = \"10.0.0.7\"").unwrap();
+        assert!(resolve.check_proxy(no_env).is_ok());
+        let err = resolve
+            .check_proxy(|var| (var == "https_proxy").then(|| "http://proxy:3128".to_string()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("https_proxy"), "{}", err);
+
+        // even behind an HTTP one, since it'd still be skipped for some requests
+        let env_socks = |var: &str| match var {
+            "HTTP_PROXY" => Some("http://proxy:3128".to_string()),
+            "ALL_PROXY" => Some("SOCKS5://tor:9050".to_string()),
+            _ => None,
+        };
+        let err = Config::default()
+            .check_proxy(env_socks)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("ALL_PROXY unset"), "{}", err);
+        assert!(Config::default().check_proxy(no_env).is_ok());
+        // the config's own proxy is the one used, so the environment's doesn't matter
+        let http: Config = toml::from_str(r#"proxy = "http://proxy:3128""#).unwrap();
+        assert!(http.check_proxy(env_socks).is_ok());
+    }
+
+    #[test]
+    fn session_key_is_replaced_under_lastfm_only() {
+        let path =
+            std::env::temp_dir().join(format!("plaza-config-test-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[headers]\nsession_key = \"relay\"\n\n[lastfm]\napi_key = \"k\"\napi_secret = \"s\"\nsession_key = \"old\"\n",
+        )
+        .unwrap();
+        Config::save_lastfm_session(&path, "new").unwrap();
+        let config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            config.headers.get("session_key").map(String::as_str),
+            Some("relay")
+        );
+        assert_eq!(config.lastfm.unwrap().session_key.as_deref(), Some("new"));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::bus::Feed;
+use crate::config::Http;
+use crate::icy::{self, IcyReader};
+use anyhow::{anyhow, Result};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tokio::sync::watch;
+
+/// Decodes `url` until the stream ends or `keep_going` returns false. `open` is called
+/// with the channel count and sample rate once the first packet is decoded and
+/// returns the feed the interleaved samples should go to. Stream titles sent
+/// along with the audio go to `titles`.
+pub fn decode_stream(
+    url: &str,
+    http: &Http,
+    titles: watch::Sender<Option<String>>,
+    keep_going: impl Fn() -> bool,
+    mut open: impl FnMut(u16, u32) -> Feed,
+) -> Result<()> {
+    // the body of a live stream never finishes, so no overall timeout
+    let resp = icy::connect(url, None, http)?;
+
+    let mut hint = Hint::new();
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if content_type.contains("mpeg") || url.ends_with("mp3") {
+        hint.with_extension("mp3");
+    } else if content_type.contains("ogg") || url.ends_with("ogg") {
+        hint.with_extension("ogg");
+    } else if content_type.contains("flac") || url.ends_with("flac") {
+        hint.with_extension("flac");
+    }
+
+    let metaint = icy::metaint(&resp);
+    let audio = IcyReader::new(resp, metaint, titles);
+    let mss = MediaSourceStream::new(Box::new(ReadOnlySource::new(audio)), Default::default());
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow!("stream has no audio track"))?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut feed: Option<Feed> = None;
+    let mut sample_buf: Option<SampleBuffer<i16>> = None;
+
+    let result = loop {
+        if !keep_going() {
+            break Ok(());
+        }
+        let packet = match format.next_packet() {
+            Ok(p) => p,
+            Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break Ok(()),
+            Err(e) => break Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(d) => d,
+            // a corrupt frame in a live stream isn't worth dropping the connection for
+            Err(Error::DecodeError(_)) => continue,
+            Err(e) => break Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        let buf = match &mut sample_buf {
+            Some(buf) if buf.capacity() >= decoded.capacity() => buf,
+            _ => sample_buf.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buf.copy_interleaved_ref(decoded);
+
+        let feed = feed.get_or_insert_with(|| open(spec.channels.count() as u16, spec.rate));
+        if !feed.send(buf.samples()) {
+            break Ok(());
+        }
+    };
+
+    if let Some(feed) = &feed {
+        feed.end();
+    }
+    result
+}
@@ -0,0 +1,267 @@
+use rodio::Source;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Center frequencies; the lowest is a low shelf and the highest a high shelf.
+pub const BANDS: [f32; 5] = [60.0, 250.0, 1000.0, 4000.0, 12000.0];
+/// Each band goes this far either way, in dB.
+pub const MAX_DB: f32 = 12.0;
+
+/// Built in, ahead of any from the config.
+pub const PRESETS: &[(&str, [f32; 5])] = &[
+    ("flat", [0.0; 5]),
+    ("bass boost", [6.0, 4.0, 0.0, 0.0, 0.0]),
+    ("vocal", [-2.0, -1.0, 3.0, 3.0, 0.0]),
+];
+
+// Gains are checked this often, and move at most `GLIDE_DB` each time, so a
+// change sweeps in over a few tens of milliseconds instead of clicking.
+const BLOCK_FRAMES: u32 = 64;
+const GLIDE_DB: f32 = 0.25;
+const Q: f32 = 1.0;
+
+/// Every preset's name, built-in ones first, then `custom` alphabetically.
+pub fn preset_names(custom: &HashMap<String, [f32; 5]>) -> Vec<String> {
+    let mut custom: Vec<&String> = custom.keys().collect();
+    custom.sort();
+    PRESETS
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .chain(custom.into_iter().cloned())
+        .collect()
+}
+
+/// The gains of the preset called `name`; the config's win over built-in ones.
+pub fn preset(name: &str, custom: &HashMap<String, [f32; 5]>) -> Option<[f32; 5]> {
+    custom.get(name).copied().or_else(|| {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, gains)| *gains)
+    })
+}
+
+/// Band gains in dB, shared between whoever sets them and the playing source.
+#[derive(Default)]
+pub struct EqGains([AtomicU32; 5]);
+
+impl EqGains {
+    pub fn new(gains: [f32; 5]) -> Self {
+        let shared = Self::default();
+        shared.set(gains);
+        shared
+    }
+
+    pub fn get(&self) -> [f32; 5] {
+        std::array::from_fn(|band| f32::from_bits(self.0[band].load(Ordering::Relaxed)))
+    }
+
+    pub fn set(&self, gains: [f32; 5]) {
+        for (band, db) in gains.into_iter().enumerate() {
+            self.set_band(band, db);
+        }
+    }
+
+    pub fn set_band(&self, band: usize, db: f32) {
+        self.0[band].store(db.clamp(-MAX_DB, MAX_DB).to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Normalized biquad coefficients: b0, b1, b2, a1, a2.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Coefficients([f32; 5]);
+
+impl Coefficients {
+    /// From the Audio EQ Cookbook, with shelves at the ends.
+    fn new(band: usize, db: f32, sample_rate: u32) -> Self {
+        let freq = BANDS[band].min(sample_rate as f32 * 0.45);
+        let a = 10f32.powf(db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * Q);
+
+        let [b0, b1, b2, a0, a1, a2] = if band == 0 || band == BANDS.len() - 1 {
+            let shelf = 2.0 * a.sqrt() * alpha;
+            // the high shelf is the low one with the sign of cos flipped
+            let sign = if band == 0 { 1.0 } else { -1.0 };
+            let cos = sign * cos;
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                sign * 2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                sign * -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ]
+        } else {
+            [
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ]
+        };
+        Coefficients([b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0])
+    }
+}
+
+/// Runs `inner` through the equalizer set by `gains`. While every band is at
+/// 0 dB the samples pass through untouched.
+pub struct Equalized<S> {
+    inner: S,
+    gains: Arc<EqGains>,
+    // where each band's gain has got to on its way to `gains`
+    current: [f32; 5],
+    coefficients: [Coefficients; 5],
+    // x1, x2, y1, y2 for each band of each channel
+    history: Vec<[[f32; 4]; 5]>,
+    channel: usize,
+    frames_left: u32,
+}
+
+impl<S: Source<Item = f32>> Equalized<S> {
+    pub fn new(inner: S, gains: Arc<EqGains>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate().max(1);
+        Equalized {
+            inner,
+            gains,
+            current: [0.0; 5],
+            coefficients: std::array::from_fn(|band| Coefficients::new(band, 0.0, sample_rate)),
+            history: vec![[[0.0; 4]; 5]; channels],
+            channel: 0,
+            frames_left: 0,
+        }
+    }
+
+    /// Moves each band a step towards its gain.
+    fn glide(&mut self) {
+        let sample_rate = self.inner.sample_rate().max(1);
+        for (band, target) in self.gains.get().into_iter().enumerate() {
+            let diff = target - self.current[band];
+            if diff == 0.0 {
+                continue;
+            }
+            self.current[band] += diff.clamp(-GLIDE_DB, GLIDE_DB);
+            self.coefficients[band] = Coefficients::new(band, self.current[band], sample_rate);
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Equalized<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        if self.channel == 0 {
+            if self.frames_left == 0 {
+                self.glide();
+                self.frames_left = BLOCK_FRAMES;
+            }
+            self.frames_left -= 1;
+        }
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.history.len();
+
+        if self.current.iter().all(|&db| db == 0.0) {
+            return Some(sample);
+        }
+
+        let mut x = sample;
+        for (Coefficients([b0, b1, b2, a1, a2]), h) in self
+            .coefficients
+            .iter()
+            .zip(self.history[channel].iter_mut())
+        {
+            let y = b0 * x + b1 * h[0] + b2 * h[1] - a1 * h[2] - a2 * h[3];
+            *h = [x, h[0], y, h[2]];
+            x = y;
+        }
+        Some(x)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Equalized<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    /// The RMS of the last half second of a `freq` sine through the EQ.
+    fn level_through(freq: f32, gains: [f32; 5]) -> f32 {
+        let sine = SineWave::new(freq).amplify(0.1);
+        let rate = sine.sample_rate() as usize;
+        let samples: Vec<f32> = Equalized::new(sine, Arc::new(EqGains::new(gains)))
+            .take(rate * 2)
+            .collect();
+        rms(&samples[rate * 3 / 2..])
+    }
+
+    #[test]
+    fn flat_passes_samples_through() {
+        let sine = SineWave::new(440.0);
+        let expected: Vec<f32> = sine.clone().take(10_000).collect();
+        let samples: Vec<f32> = Equalized::new(sine, Arc::new(EqGains::new([0.0; 5])))
+            .take(10_000)
+            .collect();
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn bands_boost_and_cut_their_own_frequencies() {
+        let flat = level_through(250.0, [0.0; 5]);
+        let boosted = level_through(250.0, [0.0, 12.0, 0.0, 0.0, 0.0]);
+        let db = 20.0 * (boosted / flat).log10();
+        assert!((db - 12.0).abs() < 1.5, "250 Hz boosted by {} dB", db);
+
+        // a shelf is halfway at its frequency and all the way well past it
+        let flat = level_through(15.0, [0.0; 5]);
+        let boosted = level_through(15.0, [12.0, 0.0, 0.0, 0.0, 0.0]);
+        let db = 20.0 * (boosted / flat).log10();
+        assert!(db > 9.0, "15 Hz boosted by {} dB", db);
+        let flat = level_through(18000.0, [0.0; 5]);
+        let cut = level_through(18000.0, [0.0, 0.0, 0.0, 0.0, -12.0]);
+        let db = 20.0 * (cut / flat).log10();
+        assert!(db < -9.0, "18 kHz cut by {} dB", db);
+
+        let flat = level_through(1000.0, [0.0; 5]);
+        let cut = level_through(1000.0, [0.0, 0.0, -12.0, 0.0, 0.0]);
+        let db = 20.0 * (cut / flat).log10();
+        assert!((db + 12.0).abs() < 1.5, "1 kHz cut by {} dB", db);
+
+        // far from the band, barely anything changes
+        let untouched = level_through(1000.0, [12.0, 0.0, 0.0, 0.0, 0.0]);
+        assert!((20.0 * (untouched / flat).log10()).abs() < 1.0);
+    }
+
+    #[test]
+    fn config_presets_win() {
+        let custom = HashMap::from([("flat".to_string(), [1.0; 5])]);
+        assert_eq!(preset("flat", &custom), Some([1.0; 5]));
+        assert_eq!(preset("vocal", &custom), Some(PRESETS[2].1));
+        assert_eq!(preset("loud", &custom), None);
+    }
+}
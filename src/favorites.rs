@@ -0,0 +1,102 @@
+use crate::metadata::NowPlaying;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Favorite {
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub liked_at: u64,
+}
+
+impl Favorite {
+    fn is(&self, np: &NowPlaying) -> bool {
+        self.artist == np.artist && self.title == np.title
+    }
+}
+
+/// Where liked tracks are kept: one JSON object per line, next to the config.
+pub fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("plaza_radio_tui").join("favorites.jsonl"))
+}
+
+/// Every liked track, oldest first. A missing file is an empty list.
+pub fn load(path: &Path) -> Result<Vec<Favorite>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!("Invalid favorite on line {} of {}", i + 1, path.display())
+            })
+        })
+        .collect()
+}
+
+/// Appends `np` unless it's already there. Returns whether it was added.
+pub fn add(path: &Path, np: &NowPlaying) -> Result<bool> {
+    if load(path)?.iter().any(|f| f.is(np)) {
+        return Ok(false);
+    }
+    let favorite = Favorite {
+        artist: np.artist.clone(),
+        title: np.title.clone(),
+        liked_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&favorite)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn liking_twice_saves_once() {
+        let dir = std::env::temp_dir().join(format!("plaza_fav_test_{}", std::process::id()));
+        let path = dir.join("favorites.jsonl");
+        let track = |title: &str| NowPlaying {
+            artist: Some("Macintosh Plus".to_string()),
+            title: Some(title.to_string()),
+            ..Default::default()
+        };
+
+        assert!(load(&path).unwrap().is_empty());
+        assert!(add(&path, &track("リサフランク420")).unwrap());
+        assert!(!add(&path, &track("リサフランク420")).unwrap());
+        assert!(add(&path, &track("花の専門家")).unwrap());
+
+        let titles: Vec<_> = load(&path)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.title.unwrap())
+            .collect();
+        assert_eq!(titles, ["リサフランク420", "花の専門家"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
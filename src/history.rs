@@ -0,0 +1,136 @@
+use crate::config::HistoryFormat;
+use crate::metadata::NowPlaying;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub track: NowPlaying,
+    pub started_at: SystemTime,
+    pub listened: Duration,
+}
+
+// Older entries are dropped past this.
+const MAX_ENTRIES: usize = 50;
+
+/// Tracks heard this session. A track is only recorded once it's over and
+/// was listened to for at least `min_listen`.
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    current: Option<(NowPlaying, SystemTime, Instant)>,
+    min_listen: Duration,
+}
+
+impl History {
+    pub fn new(min_listen: Duration) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(MAX_ENTRIES),
+            current: None,
+            min_listen,
+        }
+    }
+
+    /// Feed every metadata value seen; a change of track closes out the previous one.
+    pub fn observe(&mut self, np: &NowPlaying) {
+        if let Some((current, _, _)) = &self.current {
+            if current.same_track(np) {
+                return;
+            }
+        }
+
+        self.finish();
+
+        if np.artist.is_some() || np.title.is_some() {
+            self.current = Some((np.clone(), SystemTime::now(), Instant::now()));
+        }
+    }
+
+    /// Closes out the track that's playing now, e.g. when quitting.
+    pub fn finish(&mut self) {
+        if let Some((track, started_at, since)) = self.current.take() {
+            let listened = since.elapsed();
+            if listened < self.min_listen {
+                return;
+            }
+
+            // A station ID that comes round again straight after itself is one entry.
+            if let Some(last) = self.entries.back_mut() {
+                if last.track.same_track(&track) {
+                    last.listened += listened;
+                    return;
+                }
+            }
+
+            if self.entries.len() == MAX_ENTRIES {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(HistoryEntry {
+                track,
+                started_at,
+                listened,
+            });
+        }
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> &VecDeque<HistoryEntry> {
+        &self.entries
+    }
+}
+
+#[derive(Serialize)]
+struct ExportRecord<'a> {
+    /// Seconds since the Unix epoch.
+    started_at: u64,
+    artist: Option<&'a str>,
+    title: Option<&'a str>,
+    listened_secs: u64,
+}
+
+/// Writes every entry to `path` as JSON or CSV.
+pub fn export(history: &History, path: &Path, format: HistoryFormat) -> Result<()> {
+    let records: Vec<ExportRecord> = history
+        .entries()
+        .iter()
+        .map(|entry| ExportRecord {
+            started_at: entry
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            artist: entry.track.artist.as_deref(),
+            title: entry.track.title.as_deref(),
+            listened_secs: entry.listened.as_secs(),
+        })
+        .collect();
+
+    let text = match format {
+        HistoryFormat::Json => serde_json::to_string_pretty(&records)?,
+        HistoryFormat::Csv => {
+            let mut text = String::from("started_at,artist,title,listened_secs\n");
+            for r in &records {
+                text.push_str(&format!(
+                    "{},{},{},{}\n",
+                    r.started_at,
+                    csv_field(r.artist.unwrap_or_default()),
+                    csv_field(r.title.unwrap_or_default()),
+                    r.listened_secs
+                ));
+            }
+            text
+        }
+    };
+
+    std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
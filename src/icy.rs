@@ -0,0 +1,177 @@
+use crate::config::Http;
+use anyhow::Result;
+use reqwest::blocking::Response;
+use std::io::{self, Read};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Requests `url` asking the server to interleave ICY metadata with the audio.
+/// `read_timeout` bounds each read, so a silent connection fails instead of
+/// hanging.
+pub fn connect(url: &str, read_timeout: Option<Duration>, http: &Http) -> Result<Response> {
+    let client = http
+        .blocking_client()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(read_timeout)
+        .build()?;
+    let resp = client
+        .get(url)
+        .header("Icy-MetaData", "1")
+        .send()?
+        .error_for_status()?;
+    Ok(resp)
+}
+
+/// How many audio bytes the server sends between metadata blocks, if it
+/// sends any metadata at all.
+pub fn metaint(resp: &Response) -> Option<usize> {
+    resp.headers()
+        .get("icy-metaint")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&n| n > 0)
+}
+
+/// Passes the audio through with the metadata blocks taken out, publishing
+/// each new `StreamTitle` to `titles`.
+pub struct IcyReader<R> {
+    inner: R,
+    metaint: Option<usize>,
+    // audio bytes left before the next metadata block
+    until_meta: usize,
+    titles: watch::Sender<Option<String>>,
+}
+
+impl<R: Read> IcyReader<R> {
+    /// Without a `metaint` the stream has no metadata and is passed through as is.
+    pub fn new(inner: R, metaint: Option<usize>, titles: watch::Sender<Option<String>>) -> Self {
+        IcyReader {
+            inner,
+            metaint,
+            until_meta: metaint.unwrap_or(0),
+            titles,
+        }
+    }
+
+    /// Reads one metadata block: a length byte counting 16-byte units, then
+    /// the text, padded with NULs. Returns false at the end of the stream.
+    fn read_metadata(&mut self) -> io::Result<bool> {
+        let mut len = [0u8; 1];
+        if self.inner.read(&mut len)? == 0 {
+            return Ok(false);
+        }
+        let mut block = vec![0u8; len[0] as usize * 16];
+        self.inner.read_exact(&mut block)?;
+
+        if let Some(title) = stream_title(&block) {
+            self.titles.send_if_modified(|current| {
+                if current.as_deref() == Some(title.as_str()) {
+                    return false;
+                }
+                *current = Some(title);
+                true
+            });
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for IcyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(metaint) = self.metaint else {
+            return self.inner.read(buf);
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.until_meta == 0 {
+            if !self.read_metadata()? {
+                return Ok(0);
+            }
+            self.until_meta = metaint;
+        }
+
+        let want = buf.len().min(self.until_meta);
+        let n = self.inner.read(&mut buf[..want])?;
+        self.until_meta -= n;
+        Ok(n)
+    }
+}
+
+/// The title out of a block like `StreamTitle='Artist - Title';StreamUrl='';`.
+/// Empty titles (some stations send them between songs) count as none.
+pub fn stream_title(block: &[u8]) -> Option<String> {
+    const KEY: &str = "StreamTitle='";
+
+    let text = String::from_utf8_lossy(block);
+    let text = text.trim_end_matches('\0');
+    let rest = &text[text.find(KEY)? + KEY.len()..];
+    // titles can contain quotes themselves, so look for the end of the field
+    let end = rest
+        .find("';")
+        .or_else(|| rest.rfind('\''))
+        .unwrap_or(rest.len());
+    let title = rest[..end].trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(text: &str) -> Vec<u8> {
+        let mut padded = text.as_bytes().to_vec();
+        padded.resize(text.len().div_ceil(16) * 16, 0);
+        let mut block = vec![(padded.len() / 16) as u8];
+        block.extend(padded);
+        block
+    }
+
+    #[test]
+    fn stream_title_fields() {
+        assert_eq!(
+            stream_title(b"StreamTitle='Artist - Title';StreamUrl='';\0\0\0"),
+            Some("Artist - Title".to_string())
+        );
+        assert_eq!(
+            stream_title(b"StreamTitle='Don't Stop';"),
+            Some("Don't Stop".to_string())
+        );
+        assert_eq!(stream_title(b"StreamTitle='';"), None);
+        assert_eq!(stream_title(b"StreamUrl='x';"), None);
+    }
+
+    #[test]
+    fn reader_strips_metadata_and_publishes_titles() {
+        let mut stream = b"abcd".to_vec();
+        stream.extend(block("StreamTitle='A - B';"));
+        stream.extend(b"efgh");
+        stream.push(0); // empty block
+        stream.extend(b"ij");
+
+        let (tx, rx) = watch::channel(None);
+        let mut audio = Vec::new();
+        IcyReader::new(stream.as_slice(), Some(4), tx)
+            .read_to_end(&mut audio)
+            .unwrap();
+
+        assert_eq!(audio, b"abcdefghij");
+        assert_eq!(rx.borrow().as_deref(), Some("A - B"));
+    }
+
+    #[test]
+    fn reader_without_metaint_passes_through() {
+        let (tx, rx) = watch::channel(None);
+        let mut audio = Vec::new();
+        IcyReader::new(&b"StreamTitle='x';"[..], None, tx)
+            .read_to_end(&mut audio)
+            .unwrap();
+
+        assert_eq!(audio, b"StreamTitle='x';");
+        assert_eq!(*rx.borrow(), None);
+    }
+}
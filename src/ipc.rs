@@ -0,0 +1,308 @@
+use crate::metadata::{NowPlaying, Watcher};
+use crate::mpris::{Command, Status};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, watch};
+
+/// What `--status` prints without `--format`.
+pub const DEFAULT_FORMAT: &str = "{state} {track} [{volume}%]";
+
+/// The player answers straight away; anything slower is stuck.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One line of what's sent to scripts.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Update {
+    #[serde(flatten)]
+    pub track: NowPlaying,
+    pub paused: bool,
+    pub volume: f32,
+}
+
+/// Listens on a Unix socket at `path`. Each client is sent the status as a
+/// line of JSON straight away and again whenever it changes, and can send
+/// back "play", "pause", "toggle", "stop", "vol 0.7" or "vol +0.05", one per
+/// line. `--status` can ask at any time, so `watcher` keeps metadata coming
+/// while the socket is up.
+pub fn spawn(
+    path: &Path,
+    commands: mpsc::UnboundedSender<Command>,
+    status: watch::Receiver<Status>,
+    watcher: Watcher,
+) -> Result<()> {
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => bail!("Another player is already listening on {}", path.display()),
+        // left behind by a previous run that didn't get to clean up
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        // nothing there, or something bind will complain about
+        Err(_) => {}
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+
+    let path = path.to_path_buf();
+    tokio::spawn(async move {
+        let _cleanup = RemoveOnDrop(path);
+        let _watcher = watcher;
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, commands.clone(), status.clone()));
+        }
+    });
+    Ok(())
+}
+
+struct RemoveOnDrop(PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Talks to one client until it hangs up.
+async fn serve(
+    stream: UnixStream,
+    commands: mpsc::UnboundedSender<Command>,
+    mut status: watch::Receiver<Status>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    status.mark_changed();
+
+    loop {
+        let reply = tokio::select! {
+            changed = status.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let status = status.borrow_and_update().clone();
+                let update = Update {
+                    track: status.track,
+                    paused: status.paused,
+                    volume: status.volume,
+                };
+                match serde_json::to_string(&update) {
+                    Ok(json) => json,
+                    Err(_) => continue,
+                }
+            }
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    return;
+                };
+                match parse_command(&line) {
+                    Ok(None) => continue,
+                    Ok(Some(command)) => {
+                        let _ = commands.send(command);
+                        continue;
+                    }
+                    Err(e) => serde_json::json!({ "error": e }).to_string(),
+                }
+            }
+        };
+        if writer
+            .write_all(format!("{}\n", reply).as_bytes())
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Asks the player listening at `path` what it's playing.
+pub async fn query(path: &Path) -> Result<Update> {
+    let stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("Failed to connect to {}", path.display()))?;
+    let mut line = String::new();
+    tokio::time::timeout(QUERY_TIMEOUT, BufReader::new(stream).read_line(&mut line))
+        .await
+        .context("No answer from the player")?
+        .context("Failed to read from the player")?;
+    serde_json::from_str(&line).context("Invalid status from the player")
+}
+
+/// Hands `command` to the player listening at `path`, after checking it's one
+/// the player understands. An empty one is an error too, so a binding that
+/// sends nothing doesn't pass for one that works.
+pub async fn send(path: &Path, command: &str) -> Result<()> {
+    if parse_command(command)
+        .map_err(anyhow::Error::msg)?
+        .is_none()
+    {
+        bail!("No command given");
+    }
+    let mut stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("Failed to connect to {}", path.display()))?;
+    stream
+        .write_all(format!("{}\n", command.trim()).as_bytes())
+        .await
+        .with_context(|| format!("Failed to send to {}", path.display()))
+}
+
+/// `template` with `{artist}`, `{title}`, `{track}` ("Artist — Title", or
+/// whichever is known), `{state}` (▶ or ⏸) and `{volume}` (in percent)
+/// filled in. Anything else in braces is left as it is.
+pub fn format_status(template: &str, update: &Update) -> String {
+    let NowPlaying { artist, title, .. } = &update.track;
+    let field = |name: &str| {
+        Some(match name {
+            "artist" => artist.clone().unwrap_or_default(),
+            "title" => title.clone().unwrap_or_default(),
+            "track" => match (artist, title) {
+                (Some(a), Some(t)) => format!("{} — {}", a, t),
+                (Some(one), None) | (None, Some(one)) => one.clone(),
+                (None, None) => String::new(),
+            },
+            "state" => if update.paused { "⏸" } else { "▶" }.to_string(),
+            "volume" => format!("{:.0}", update.volume * 100.0),
+            _ => return None,
+        })
+    };
+
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let filled = rest
+            .find('}')
+            .and_then(|end| Some((field(&rest[1..end])?, end)));
+        match filled {
+            Some((value, end)) => {
+                out.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `None` for a blank line.
+fn parse_command(line: &str) -> Result<Option<Command>, String> {
+    let mut words = line.split_whitespace();
+    let command = match (words.next(), words.next()) {
+        (None, _) => return Ok(None),
+        (Some("play"), None) => Command::Play,
+        (Some("pause"), None) => Command::Pause,
+        (Some("toggle"), None) => Command::PlayPause,
+        (Some("stop"), None) => Command::Stop,
+        (Some("vol"), Some(volume)) => match volume.parse::<f32>() {
+            Ok(v) if !v.is_finite() => return Err(format!("not a volume: {}", volume)),
+            // a sign makes it a step up or down
+            Ok(step) if volume.starts_with(['+', '-']) => Command::ChangeVolume(step),
+            Ok(volume) => Command::SetVolume(volume),
+            Err(_) => return Err(format!("not a volume: {}", volume)),
+        },
+        _ => return Err(format!("unknown command: {}", line.trim())),
+    };
+    if words.next().is_some() {
+        return Err(format!("unknown command: {}", line.trim()));
+    }
+    Ok(Some(command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::PollDemand;
+    use std::sync::Arc;
+
+    #[test]
+    fn commands_from_scripts() {
+        assert_eq!(parse_command("pause"), Ok(Some(Command::Pause)));
+        assert_eq!(
+            parse_command("  vol 0.7\r"),
+            Ok(Some(Command::SetVolume(0.7)))
+        );
+        assert_eq!(parse_command(""), Ok(None));
+        assert_eq!(
+            parse_command("vol +0.05"),
+            Ok(Some(Command::ChangeVolume(0.05)))
+        );
+        assert_eq!(
+            parse_command("vol -0.1"),
+            Ok(Some(Command::ChangeVolume(-0.1)))
+        );
+        assert!(parse_command("vol loud").is_err());
+        assert!(parse_command("vol nan").is_err());
+        assert!(parse_command("vol +inf").is_err());
+        assert!(parse_command("play now").is_err());
+        assert!(parse_command("skip").is_err());
+    }
+
+    #[tokio::test]
+    async fn sending_nothing_is_an_error() {
+        let nowhere = Path::new("/nonexistent/plaza.sock");
+        let e = send(nowhere, "  ").await.unwrap_err();
+        assert_eq!(e.to_string(), "No command given");
+        assert!(send(nowhere, "skip").await.is_err());
+    }
+
+    #[test]
+    fn status_lines_for_status_bars() {
+        let mut update = Update {
+            track: NowPlaying {
+                artist: Some("Macross 82-99".to_string()),
+                title: Some("{title}".to_string()),
+                ..Default::default()
+            },
+            paused: false,
+            volume: 0.75,
+        };
+        assert_eq!(
+            format_status(DEFAULT_FORMAT, &update),
+            "▶ Macross 82-99 — {title} [75%]"
+        );
+        update.paused = true;
+        update.track.artist = None;
+        assert_eq!(
+            format_status("{state} {artist}|{title} {vol} {", &update),
+            "⏸ |{title} {vol} {"
+        );
+    }
+
+    #[tokio::test]
+    async fn status_is_queried_from_the_socket() {
+        let path = std::env::temp_dir().join(format!("plaza-ipc-test-{}.sock", std::process::id()));
+        let (commands, _) = mpsc::unbounded_channel();
+        let (_status_tx, status) = watch::channel(Status {
+            track: NowPlaying {
+                title: Some("Telephone Call".to_string()),
+                ..Default::default()
+            },
+            paused: false,
+            volume: 0.5,
+        });
+        let demand = Arc::new(PollDemand::default());
+        // a socket nobody is listening on any more is taken over
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        spawn(&path, commands.clone(), status.clone(), demand.watch()).unwrap();
+
+        // a second player leaves the first one's socket alone
+        let err = spawn(&path, commands, status, demand.watch()).unwrap_err();
+        assert!(err.to_string().contains("already listening"));
+
+        let update = query(&path).await.unwrap();
+        assert_eq!(update.track.title.as_deref(), Some("Telephone Call"));
+        assert_eq!(
+            format_status(DEFAULT_FORMAT, &update),
+            "▶ Telephone Call [50%]"
+        );
+    }
+}
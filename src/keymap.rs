@@ -0,0 +1,392 @@
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Retry,
+    TogglePause,
+    VolumeUp,
+    VolumeDown,
+    VolumeUpFine,
+    VolumeDownFine,
+    Mute,
+    Duck,
+    ToggleNormalize,
+    ToggleMono,
+    ToggleEq,
+    FlushToLive,
+    ToggleRecording,
+    Like,
+    OpenSearch,
+    ToggleHistory,
+    SwapArtistTitle,
+    ToggleDetails,
+    ToggleArtOnly,
+    ToggleCompactView,
+    ToggleCompactControls,
+    ToggleHelp,
+    SleepTimer,
+    BrowseOlder,
+    BrowseNewer,
+    BackToLive,
+    NextStation,
+    PrevStation,
+}
+
+impl Action {
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Retry => "retry after the stream was given up on",
+            Action::TogglePause => "pause/resume",
+            Action::VolumeUp => "volume up",
+            Action::VolumeDown => "volume down",
+            Action::VolumeUpFine => "fine volume up",
+            Action::VolumeDownFine => "fine volume down",
+            Action::Mute => "mute/unmute",
+            Action::Duck => "duck/restore",
+            Action::ToggleNormalize => "loudness normalization on/off",
+            Action::ToggleMono => "mono/stereo",
+            Action::ToggleEq => "equalizer",
+            Action::FlushToLive => "drop what's buffered and catch up with the stream",
+            Action::ToggleRecording => "start/stop recording",
+            Action::Like => "add the track to favorites",
+            Action::OpenSearch => "search for the track in the browser",
+            Action::ToggleHistory => "history",
+            Action::SwapArtistTitle => "swap artist/title",
+            Action::ToggleDetails => "details",
+            Action::ToggleArtOnly => "art-only view",
+            Action::ToggleCompactView => "compact view",
+            Action::ToggleCompactControls => "compact controls",
+            Action::ToggleHelp => "this help",
+            Action::SleepTimer => "set/cancel the sleep timer",
+            Action::BrowseOlder => "older cover",
+            Action::BrowseNewer => "newer cover",
+            Action::BackToLive => "back to the live track",
+            Action::NextStation => "next station",
+            Action::PrevStation => "previous station",
+        }
+    }
+
+    /// Whether the action only does anything while audio is playing.
+    pub fn needs_audio(self) -> bool {
+        matches!(
+            self,
+            Action::Retry
+                | Action::TogglePause
+                | Action::VolumeUp
+                | Action::VolumeDown
+                | Action::VolumeUpFine
+                | Action::VolumeDownFine
+                | Action::Mute
+                | Action::Duck
+                | Action::ToggleNormalize
+                | Action::ToggleMono
+                | Action::ToggleEq
+                | Action::FlushToLive
+                | Action::ToggleRecording
+        )
+    }
+}
+
+/// Every action with its default keys, in the order the help lists them.
+///
+/// Volume up answers to `+` and `=` (so it works whether or not `+` needs
+/// Shift on the current layout) as well as the keypad `+`, which crossterm
+/// reports as a plain `+` with an extra keypad state flag.
+const DEFAULTS: &[(Action, &[KeyCode])] = &[
+    (Action::TogglePause, &[KeyCode::Char(' ')]),
+    (Action::VolumeUp, &[KeyCode::Char('+'), KeyCode::Char('=')]),
+    (
+        Action::VolumeDown,
+        &[KeyCode::Char('-'), KeyCode::Char('_')],
+    ),
+    (Action::VolumeUpFine, &[KeyCode::Up]),
+    (Action::VolumeDownFine, &[KeyCode::Down]),
+    (Action::Mute, &[KeyCode::Char('m')]),
+    (Action::Duck, &[KeyCode::Char('d')]),
+    (Action::ToggleNormalize, &[KeyCode::Char('g')]),
+    (Action::ToggleMono, &[KeyCode::Char('M')]),
+    (Action::ToggleEq, &[KeyCode::Char('e')]),
+    (Action::FlushToLive, &[KeyCode::Char('l')]),
+    (Action::ToggleRecording, &[KeyCode::Char('r')]),
+    (Action::Like, &[KeyCode::Char('f')]),
+    (Action::OpenSearch, &[KeyCode::Char('o')]),
+    (Action::ToggleHistory, &[KeyCode::Char('h')]),
+    (Action::SwapArtistTitle, &[KeyCode::Char('s')]),
+    (Action::ToggleDetails, &[KeyCode::Char('i')]),
+    (Action::ToggleArtOnly, &[KeyCode::Char('a')]),
+    (Action::ToggleCompactView, &[KeyCode::Char('c')]),
+    (Action::ToggleCompactControls, &[KeyCode::Char('k')]),
+    (Action::BrowseOlder, &[KeyCode::Char('[')]),
+    (Action::BrowseNewer, &[KeyCode::Char(']')]),
+    (Action::BackToLive, &[KeyCode::Esc]),
+    (Action::NextStation, &[KeyCode::Char('n')]),
+    (Action::PrevStation, &[KeyCode::Char('p')]),
+    (Action::SleepTimer, &[KeyCode::Char('t')]),
+    (Action::Retry, &[KeyCode::Char('R')]),
+    (Action::ToggleHelp, &[KeyCode::Char('?')]),
+    (Action::Quit, &[KeyCode::Char('q')]),
+];
+
+/// Actions shown in the controls list, what they do, and a one-word version
+/// for the compact footer.
+pub const CONTROLS: &[(&[Action], &str, &str)] = &[
+    (&[Action::TogglePause], "pause/resume", "play"),
+    (
+        &[Action::VolumeUp, Action::VolumeDown],
+        "volume up/down",
+        "vol",
+    ),
+    (&[Action::Mute], "mute/unmute", "mute"),
+    (&[Action::Duck], "duck/restore", "duck"),
+    (&[Action::ToggleNormalize], "normalize loudness", "norm"),
+    (&[Action::ToggleMono], "mono/stereo", "mono"),
+    (&[Action::ToggleEq], "equalizer", "eq"),
+    (&[Action::FlushToLive], "catch up to live", "live"),
+    (&[Action::ToggleRecording], "record", "rec"),
+    (&[Action::Like], "like", "like"),
+    (&[Action::OpenSearch], "search the web", "find"),
+    (&[Action::ToggleHistory], "history", "hist"),
+    (&[Action::SwapArtistTitle], "swap artist/title", "swap"),
+    (&[Action::ToggleDetails], "details", "info"),
+    (&[Action::ToggleArtOnly], "art-only view", "art"),
+    (&[Action::ToggleCompactView], "compact view", "mini"),
+    (&[Action::ToggleCompactControls], "compact controls", "keys"),
+    (
+        &[Action::BrowseOlder, Action::BrowseNewer],
+        "browse recent covers",
+        "browse",
+    ),
+    (
+        &[Action::NextStation, Action::PrevStation],
+        "next/previous station",
+        "station",
+    ),
+    (&[Action::SleepTimer], "sleep timer", "sleep"),
+    (&[Action::ToggleHelp], "all keys", "help"),
+    (&[Action::Quit], "quit", "quit"),
+];
+
+/// One key or several, as written in the `[keybindings]` table: `"x"` or
+/// `["j", "up"]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keys(pub Vec<KeyCode>);
+
+impl<'de> Deserialize<'de> for Keys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Names {
+            One(String),
+            Many(Vec<String>),
+        }
+
+        let names = match Names::deserialize(deserializer)? {
+            Names::One(name) => vec![name],
+            Names::Many(names) => names,
+        };
+        names
+            .iter()
+            .map(|name| {
+                parse_key(name).ok_or_else(|| de::Error::custom(format!("unknown key {:?}", name)))
+            })
+            .collect::<Result<_, _>>()
+            .map(Keys)
+    }
+}
+
+/// A single character stands for itself; anything else is a named key.
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+
+    let key = match name.to_ascii_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "esc" | "escape" => KeyCode::Esc,
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        other => {
+            let n = other.strip_prefix('f')?.parse().ok()?;
+            KeyCode::F(n)
+        }
+    };
+    Some(key)
+}
+
+/// How a key is written in the controls list and help.
+pub fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Up => "↑".to_string(),
+        KeyCode::Down => "↓".to_string(),
+        KeyCode::Left => "←".to_string(),
+        KeyCode::Right => "→".to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        other => format!("{:?}", other),
+    }
+}
+
+/// The default bindings with the config's `[keybindings]` applied on top.
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<KeyCode>)>,
+    actions: HashMap<KeyCode, Action>,
+}
+
+impl Keymap {
+    /// An action listed in `overrides` loses its default keys. A key the
+    /// config gives to one action is taken away from whatever had it by
+    /// default; giving it to two actions is an error.
+    pub fn new(overrides: &HashMap<Action, Keys>) -> Result<Self> {
+        let mut bindings: Vec<(Action, Vec<KeyCode>)> = DEFAULTS
+            .iter()
+            .map(|&(action, keys)| match overrides.get(&action) {
+                Some(Keys(keys)) => (action, keys.clone()),
+                None => (action, keys.to_vec()),
+            })
+            .collect();
+
+        let mut claimed: HashMap<KeyCode, Action> = HashMap::new();
+        for (action, Keys(keys)) in overrides {
+            for &key in keys {
+                if let Some(other) = claimed.insert(key, *action) {
+                    if other != *action {
+                        bail!(
+                            "key {} is bound to both {:?} and {:?}",
+                            key_name(key),
+                            other,
+                            action
+                        );
+                    }
+                }
+            }
+        }
+        for (action, keys) in &mut bindings {
+            if !overrides.contains_key(action) {
+                keys.retain(|key| !claimed.contains_key(key));
+            }
+        }
+
+        let actions = bindings
+            .iter()
+            .flat_map(|(action, keys)| keys.iter().map(move |&key| (key, *action)))
+            .collect();
+        Ok(Keymap { bindings, actions })
+    }
+
+    /// Maps a key event to what it should do. Modifiers are ignored, so
+    /// Shift doesn't get in the way of symbols on any layout.
+    pub fn action_for(&self, key: &KeyEvent) -> Option<Action> {
+        // Some platforms (Windows, kitty protocol) also report releases and repeats
+        // as separate events; only act on the initial press.
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        self.actions.get(&key.code).copied()
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, keys)| keys.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The first key of each action, e.g. "+/-" for volume up and down.
+    pub fn label(&self, actions: &[Action]) -> String {
+        actions
+            .iter()
+            .filter_map(|&action| self.keys(action).first())
+            .map(|&key| key_name(key))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Every action and all of its keys, for the help overlay.
+    pub fn bindings(&self) -> &[(Action, Vec<KeyCode>)] {
+        &self.bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn overrides(toml: &str) -> HashMap<Action, Keys> {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn defaults_accept_every_volume_up_variant() {
+        let keymap = Keymap::new(&HashMap::new()).unwrap();
+        for c in ['+', '='] {
+            assert_eq!(
+                keymap.action_for(&press(KeyCode::Char(c))),
+                Some(Action::VolumeUp)
+            );
+        }
+        assert_eq!(keymap.label(&[Action::VolumeUp, Action::VolumeDown]), "+/-");
+    }
+
+    #[test]
+    fn remapped_key_is_taken_from_its_default_action() {
+        let keymap = Keymap::new(&overrides(
+            r#"
+            quit = "x"
+            volume_up = ["k", "up"]
+            "#,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            keymap.action_for(&press(KeyCode::Char('x'))),
+            Some(Action::Quit)
+        );
+        assert_eq!(keymap.action_for(&press(KeyCode::Char('q'))), None);
+        assert_eq!(
+            keymap.action_for(&press(KeyCode::Char('k'))),
+            Some(Action::VolumeUp)
+        );
+        assert_eq!(
+            keymap.action_for(&press(KeyCode::Up)),
+            Some(Action::VolumeUp)
+        );
+        assert!(keymap.keys(Action::ToggleCompactControls).is_empty());
+        assert!(keymap.keys(Action::VolumeUpFine).is_empty());
+    }
+
+    #[test]
+    fn bad_bindings_are_rejected() {
+        assert!(toml::from_str::<HashMap<Action, Keys>>(r#"quit = "hyper""#).is_err());
+        assert!(Keymap::new(&overrides(
+            r#"
+            quit = "x"
+            mute = "x"
+            "#
+        ))
+        .is_err());
+    }
+}
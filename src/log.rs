@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use std::fmt::{self, Write as _};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Appends this crate's log events to `path`, from info up, or debug up with
+/// `verbose`. Until this is called nothing is logged anywhere, so nothing
+/// ever lands on top of the TUI.
+pub fn init(path: &Path, verbose: bool) -> Result<()> {
+    let log = FileLog::open(path, if verbose { Level::DEBUG } else { Level::INFO })?;
+    tracing::subscriber::set_global_default(log).context("Failed to set up logging")
+}
+
+/// One line per event: time, level, module, message and fields.
+struct FileLog {
+    file: Mutex<Box<dyn Write + Send>>,
+    max_level: Level,
+}
+
+impl FileLog {
+    fn open(path: &Path, max_level: Level) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        Ok(FileLog {
+            file: Mutex::new(Box::new(file)),
+            max_level,
+        })
+    }
+}
+
+impl Subscriber for FileLog {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // dependencies log through tracing too; only ours are wanted
+        *metadata.level() <= self.max_level
+            && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    // there are no spans, just events
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut line = Line::default();
+        event.record(&mut line);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let metadata = event.metadata();
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{}.{:03} {:>5} {}: {}{}",
+                now.as_secs(),
+                now.subsec_millis(),
+                metadata.level(),
+                metadata.target(),
+                line.message,
+                line.fields
+            );
+            let _ = file.flush();
+        }
+    }
+}
+
+/// An event's message, and its other fields as " key=value".
+#[derive(Default)]
+struct Line {
+    message: String,
+    fields: String,
+}
+
+impl Visit for Line {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Collects what's logged so the test can read it back.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn events_become_lines() {
+        let written = Shared::default();
+        let log = FileLog {
+            file: Mutex::new(Box::new(written.clone())),
+            max_level: Level::INFO,
+        };
+        tracing::subscriber::with_default(log, || {
+            tracing::warn!(
+                url = "http://radio.plaza.one/mp3",
+                attempt = 2,
+                "restarting"
+            );
+            tracing::debug!("too chatty");
+        });
+
+        let text = String::from_utf8(written.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(
+            lines[0].ends_with(
+                " WARN plaza_tui::log::tests: restarting url=\"http://radio.plaza.one/mp3\" attempt=2"
+            ),
+            "{}",
+            lines[0]
+        );
+    }
+}
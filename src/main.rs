@@ -1,44 +1,193 @@
+mod art;
+mod bus;
+mod cli;
+mod color;
+mod config;
+mod decoder;
+mod eq;
+mod favorites;
+mod history;
+mod icy;
+#[cfg(unix)]
+mod ipc;
+mod keymap;
+mod log;
 mod metadata;
+mod mpris;
+mod normalize;
 mod player;
+mod playlist;
+mod recorder;
+mod scrobble;
+mod search;
+mod state;
+mod theme;
 mod ui;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::Parser;
 use reqwest::Client;
 use std::sync::Arc;
 use tokio::sync::watch;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let client = Client::builder()
-        .user_agent("plaza_term_rs/0.2.0")
+    let cli = cli::Cli::parse();
+    if let Some(path) = &cli.log_file {
+        log::init(path, cli.verbose)?;
+    }
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), "starting");
+    let mut config = match &cli.config {
+        Some(path) => config::Config::load_from(path)?,
+        None => config::Config::load()?,
+    };
+    if let Some(url) = cli.stream {
+        config.stream_url = Some(url);
+    }
+    if let Some(device) = cli.device {
+        config.output_device = Some(device);
+    }
+    if let Some(percent) = cli.volume {
+        config.default_volume = percent as f32 / 100.0;
+    } else if let Some(saved) = state::path().and_then(|path| state::load(&path)) {
+        config.default_volume = saved.volume;
+        config.start_muted = saved.muted;
+    }
+    if let Some(backend) = cli.backend {
+        config.backend = backend;
+    }
+    if let Some(visualizer) = cli.visualizer {
+        config.visualizer = visualizer;
+    }
+    if let Some(color) = cli.color {
+        config.color = color;
+    }
+    if let Some(layout) = cli.layout {
+        config.layout = layout;
+    }
+    if cli.compact {
+        config.layout = config::LayoutMode::Compact;
+    }
+
+    if cli.list_devices {
+        for name in player::output_devices()? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    if cli.list_favorites {
+        return list_favorites(config.title_first);
+    }
+    if cli.status {
+        return print_status(&config, cli.format.as_deref()).await;
+    }
+    if let Some(command) = &cli.send {
+        return send_command(&config, command).await;
+    }
+
+    let client = config
+        .http()?
+        .client()
         .timeout(std::time::Duration::from_secs(30))
         .build()?;
 
+    if cli.lastfm_auth {
+        let config_path = cli.config.or_else(config::Config::path);
+        return scrobble::authenticate(&client, &config, config_path.as_deref()).await;
+    }
+
+    let art_base = config
+        .art_base_url
+        .as_deref()
+        .map(|url| {
+            reqwest::Url::parse(url).with_context(|| format!("Invalid art_base_url {:?}", url))
+        })
+        .transpose()?;
+
+    if cli.print_now_playing {
+        return print_now_playing(&client, art_base.as_ref(), cli.json, config.title_first).await;
+    }
+
     let (tx, rx) = watch::channel(metadata::NowPlaying::default());
-    let rx = Arc::new(tokio::sync::Mutex::new(rx));
+
+    let demand = Arc::new(metadata::PollDemand::default());
+    let (source_tx, source_rx) = watch::channel(metadata::MetadataSource::Plaza);
+    let (fetched_tx, fetched_rx) = watch::channel(None);
+    // titles embedded in the audio stream, merged in by the metadata task
+    let (titles_tx, titles_rx) = watch::channel(None);
 
     let client_for_meta = client.clone();
     let tx_meta = tx.clone();
+    let demand_meta = demand.clone();
+    let poll_settings = metadata::PollSettings {
+        every: std::time::Duration::from_secs(config.metadata_interval_secs.max(1)),
+        art_base,
+    };
     tokio::spawn(async move {
-        if let Err(e) = metadata::metadata_loop(client_for_meta, tx_meta).await {
-            eprintln!("Metadata task error: {:?}", e);
+        if let Err(e) = metadata::metadata_loop(
+            client_for_meta,
+            tx_meta,
+            fetched_tx,
+            titles_rx,
+            source_rx,
+            demand_meta,
+            poll_settings,
+        )
+        .await
+        {
+            tracing::error!("metadata task failed: {:#}", e);
         }
     });
 
-    let stream_url = player::pick_stream(&client).await.unwrap_or_else(|| {
-        println!("Using fallback stream URL");
-        "http://radio.plaza.one/mp3".to_string()
-    });
+    if let Some(lastfm) = config.lastfm.clone().filter(|l| l.session_key.is_some()) {
+        let rx = tx.subscribe();
+        let client = client.clone();
+        let min_listen = std::time::Duration::from_secs(config.min_listen_secs);
+        // scrobbling needs metadata whatever the UI shows
+        let watcher = demand.watch();
+        tokio::spawn(async move {
+            let _watcher = watcher;
+            scrobble::run(client, lastfm, min_listen, rx).await;
+        });
+    }
 
-    println!("🔗 Connecting to: {}", stream_url);
+    if cli.print {
+        // printing needs metadata whether or not anything else is watching
+        let _watcher = demand.watch();
+        print_tracks(tx.subscribe(), config.title_first).await;
+        return Ok(());
+    }
 
-    let (control, sink_info) = player::spawn_ffmpeg_to_rodio(&stream_url).map_err(|e| {
-        eprintln!("Failed to start audio player: {}", e);
-        eprintln!("Make sure you have audio drivers installed and working");
-        e
-    })?;
+    let player = if cli.no_audio {
+        None
+    } else {
+        Some(start_player(&client, &config, titles_tx).await?)
+    };
 
-    let ui_result = ui::run_ui(rx, client, control, sink_info).await;
+    let remote = mpris::spawn(demand.watch());
+    #[cfg(unix)]
+    if let Some(path) = &config.ipc_socket {
+        let (commands, status) = remote.connect();
+        if let Err(e) = ipc::spawn(path, commands, status, demand.watch()) {
+            eprintln!("⚠️  Status socket unavailable: {:#}", e);
+        }
+    }
+
+    let subscription = metadata::Subscription {
+        now_playing: rx,
+        fetched: fetched_rx,
+        demand,
+        source: source_tx,
+    };
+    let ui_result = ui::run_ui(
+        subscription,
+        remote,
+        client,
+        player,
+        config,
+        shutdown_signal(),
+    )
+    .await;
 
     if let Err(e) = ui_result {
         eprintln!("UI error: {:?}", e);
@@ -48,3 +197,196 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Resolves on SIGINT, SIGTERM or SIGHUP, so the UI can wind down (the player
+/// stopped, the terminal put back) instead of the process just dying.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let (Ok(mut term), Ok(mut hangup)) = (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::hangup()),
+        ) else {
+            return std::future::pending().await;
+        };
+        tokio::select! {
+            Ok(()) = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+            _ = hangup.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Picks and resolves the streams, then starts playing the first that works.
+async fn start_player(
+    client: &Client,
+    config: &config::Config,
+    titles_tx: watch::Sender<Option<String>>,
+) -> Result<(player::PlayerControl, player::SinkInfo)> {
+    let candidates = match &config.stream_url {
+        Some(url) => vec![url.clone()],
+        None => player::pick_streams(client, config.preferred_format.as_deref()).await,
+    };
+
+    let mut stream_urls = Vec::with_capacity(candidates.len());
+    for stream_url in candidates {
+        stream_urls.push(match playlist::resolve(client, &stream_url).await {
+            Ok(resolved) => {
+                if resolved != stream_url {
+                    println!("📃 Playlist resolved to: {}", resolved);
+                }
+                resolved
+            }
+            Err(e) => {
+                println!("Could not resolve playlist ({}), using URL as-is", e);
+                stream_url
+            }
+        });
+    }
+
+    // player.rs only logs these; they're worth seeing before the TUI starts
+    if let Some(device) = config.output_device.as_deref() {
+        if !player::has_output(device) {
+            eprintln!(
+                "⚠️  No audio output matching {:?}; using the default (see --list-devices)",
+                device
+            );
+        }
+    }
+    if eq::preset(&config.eq.preset, &config.eq.presets).is_none() {
+        eprintln!(
+            "⚠️  No EQ preset called {:?}; starting flat",
+            config.eq.preset
+        );
+    }
+
+    println!("🔗 Connecting to: {}", stream_urls[0]);
+
+    let (control, sink_info, playing) =
+        player::spawn_ffmpeg_to_rodio(&stream_urls, config, titles_tx).map_err(|e| {
+            tracing::error!("failed to start audio player: {:#}", e);
+            eprintln!("Failed to start audio player: {}", e);
+            if let Some(e) = e.downcast_ref::<player::StartError>() {
+                eprintln!("{}", e.hint());
+            }
+            e
+        })?;
+    if playing != stream_urls[0] {
+        println!("🔗 Playing: {}", playing);
+    }
+    if config.backend == config::Backend::Auto && control.backend() == config::Backend::Symphonia {
+        println!("ffmpeg isn't available; decoding with symphonia instead");
+    }
+    tracing::info!(url = %playing, backend = ?control.backend(), "stream selected");
+    Ok((control, sink_info))
+}
+
+/// For `--status`: a line about what the running player is playing, or
+/// nothing and a failing exit code if there's no player to ask.
+async fn print_status(config: &config::Config, format: Option<&str>) -> Result<()> {
+    #[cfg(unix)]
+    if let Some(path) = &config.ipc_socket {
+        match ipc::query(path).await {
+            Ok(update) => {
+                let format = format.unwrap_or(ipc::DEFAULT_FORMAT);
+                println!("{}", ipc::format_status(format, &update));
+                return Ok(());
+            }
+            Err(e) => tracing::debug!("status query failed: {:#}", e),
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (config, format);
+    std::process::exit(1);
+}
+
+/// For `--send`, e.g. from a desktop shortcut.
+async fn send_command(config: &config::Config, command: &str) -> Result<()> {
+    #[cfg(unix)]
+    if let Some(path) = &config.ipc_socket {
+        return ipc::send(path, command).await;
+    }
+    #[cfg(not(unix))]
+    let _ = (config, command);
+    anyhow::bail!("Set ipc_socket in the config to send commands to the player")
+}
+
+/// For `--print-now-playing`: one poll of the API, printed as JSON or as
+/// "artist - title". Nothing playing, or no answer, is a failing exit code.
+async fn print_now_playing(
+    client: &Client,
+    art_base: Option<&reqwest::Url>,
+    json: bool,
+    title_first: bool,
+) -> Result<()> {
+    let np = metadata::fetch_now_playing(client, &metadata::MetadataSource::Plaza, art_base)
+        .await
+        .filter(|np| np.artist.is_some() || np.title.is_some());
+    let Some(np) = np else {
+        eprintln!("Nothing is playing");
+        std::process::exit(1);
+    };
+    if json {
+        println!("{}", serde_json::to_string(&np)?);
+        return Ok(());
+    }
+    if let Some(line) = track_line(&np, title_first) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// "Artist - Title", or whichever of them is known.
+fn track_line(np: &metadata::NowPlaying, title_first: bool) -> Option<String> {
+    match np.display_parts(title_first) {
+        (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+        (Some(only), None) | (None, Some(only)) => Some(only.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// Prints every liked track, oldest first.
+fn list_favorites(title_first: bool) -> Result<()> {
+    let Some(path) = favorites::path() else {
+        return Ok(());
+    };
+    for favorite in favorites::load(&path)? {
+        let np = metadata::NowPlaying {
+            artist: favorite.artist,
+            title: favorite.title,
+            ..Default::default()
+        };
+        if let Some(line) = track_line(&np, title_first) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Prints a line for each new track until Ctrl-C.
+async fn print_tracks(mut rx: watch::Receiver<metadata::NowPlaying>, title_first: bool) {
+    let mut last = metadata::NowPlaying::default();
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return,
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let np = rx.borrow_and_update().clone();
+                if np.same_track(&last) {
+                    continue;
+                }
+                if let Some(line) = track_line(&np, title_first) {
+                    println!("{}", line);
+                }
+                last = np;
+            }
+        }
+    }
+}
@@ -1,58 +1,414 @@
 use anyhow::Result;
-use reqwest::Client;
+use rand::Rng;
+use reqwest::{Client, Url};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::time::Duration;
-use tokio::sync::watch;
-use tokio::time::interval;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, Notify};
+use tokio::time::{sleep_until, timeout};
+use tracing::warn;
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NowPlaying {
     pub artist: Option<String>,
     pub title: Option<String>,
     pub art_url: Option<String>,
+    /// The combined "A - B" stream title when artist and title were split from it.
+    pub raw_title: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    /// What's on, when the API says: "music", "talk", "jingle" and so on.
+    pub kind: Option<String>,
+    /// How long the track is and how far in it was, when the API says.
+    #[serde(skip)]
+    pub progress: Option<Progress>,
 }
 
-pub async fn metadata_loop(client: Client, tx: watch::Sender<NowPlaying>) -> Result<()> {
-    let primary_url = "https://api.plaza.one/radio/broadcast";
-    let fallback_urls = vec![
-        "https://api.plaza.one/status",
-        "https://api.plaza.one/now_playing",
-        "http://radio.plaza.one/status-json.xsl",
-    ];
+/// A position in the track as of `at`, so it can be moved along locally
+/// between polls.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub duration: Duration,
+    pub elapsed: Duration,
+    pub at: Instant,
+}
 
-    let mut ticker = interval(Duration::from_secs(5));
+impl Progress {
+    /// How far in the track is now, never past its end.
+    pub fn elapsed_now(&self) -> Duration {
+        (self.elapsed + self.at.elapsed()).min(self.duration)
+    }
+}
 
-    loop {
-        ticker.tick().await;
-
-        if let Ok(resp) = client.get(primary_url).send().await {
-            if resp.status().is_success() {
-                if let Ok(json) = resp.json::<Value>().await {
-                    if let Some(np) = parse_plaza_api(&json) {
-                        let _ = tx.send(np);
-                        continue;
-                    }
-                }
+// The position moves on by itself; only a different length counts as a change.
+impl PartialEq for Progress {
+    fn eq(&self, other: &Self) -> bool {
+        self.duration == other.duration
+    }
+}
+
+impl Eq for Progress {}
+
+impl NowPlaying {
+    /// Whether both values describe the same song, ignoring artwork changes.
+    pub fn same_track(&self, other: &NowPlaying) -> bool {
+        self.artist == other.artist && self.title == other.title
+    }
+
+    /// Whether this is the station talking (a DJ, a station ID) rather than
+    /// a song: the API says so, or there's no artist and the title is about
+    /// Plaza or being live.
+    pub fn is_live(&self) -> bool {
+        if let Some(kind) = &self.kind {
+            return !matches!(kind.as_str(), "music" | "song" | "track");
+        }
+        let title = self.title.as_deref().unwrap_or_default().to_lowercase();
+        if title.contains("station id") {
+            return true;
+        }
+        let no_artist = self.artist.as_deref().is_none_or(|a| a.trim().is_empty());
+        no_artist
+            && title
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|word| word == "plaza" || word == "live")
+    }
+
+    /// Splits a combined "Artist - Title" stream title at the first dash,
+    /// keeping the original so it can be read the other way round. Without a
+    /// dash (or with nothing on one side of it) it's all title.
+    pub fn from_stream_title(title: String) -> NowPlaying {
+        match title.split_once(" - ").map(|(a, t)| (a.trim(), t.trim())) {
+            Some((artist, t)) if !artist.is_empty() && !t.is_empty() => NowPlaying {
+                artist: Some(artist.to_string()),
+                title: Some(t.to_string()),
+                raw_title: Some(title.clone()),
+                ..Default::default()
+            },
+            _ => NowPlaying {
+                title: Some(title.trim().to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Artist and title to show. With `title_first`, a combined stream title is
+    /// read as "Title - Artist" rather than "Artist - Title".
+    pub fn display_parts(&self, title_first: bool) -> (Option<&str>, Option<&str>) {
+        if title_first {
+            if let Some((first, second)) =
+                self.raw_title.as_deref().and_then(|r| r.split_once(" - "))
+            {
+                return (Some(second.trim()), Some(first.trim()));
             }
         }
+        (self.artist.as_deref(), self.title.as_deref())
+    }
+}
 
-        for url in &fallback_urls {
-            if let Ok(resp) = client.get(*url).send().await {
-                if !resp.status().is_success() {
+/// Where now-playing information is polled from, on top of the titles sent
+/// along with the audio.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MetadataSource {
+    /// Plaza's API, with its fallbacks.
+    Plaza,
+    /// A JSON endpoint in any of the shapes `parse_plaza_api` understands.
+    Url(String),
+    /// Nothing to poll; only the stream's own titles.
+    StreamOnly,
+}
+
+/// The UI's end of the metadata task.
+pub struct Subscription {
+    pub now_playing: watch::Receiver<NowPlaying>,
+    /// When the last successful fetch was, whether or not anything changed.
+    pub fetched: watch::Receiver<Option<Instant>>,
+    pub demand: Arc<PollDemand>,
+    pub source: watch::Sender<MetadataSource>,
+}
+
+/// Counts who currently needs fresh metadata. With nobody watching, the
+/// metadata task stops polling until someone starts again.
+#[derive(Default)]
+pub struct PollDemand {
+    watchers: AtomicUsize,
+    wake: Notify,
+}
+
+impl PollDemand {
+    /// Keeps polling going for as long as the returned guard is alive.
+    pub fn watch(self: &Arc<Self>) -> Watcher {
+        self.watchers.fetch_add(1, Ordering::SeqCst);
+        self.wake.notify_one();
+        Watcher(self.clone())
+    }
+
+    fn wanted(&self) -> bool {
+        self.watchers.load(Ordering::SeqCst) > 0
+    }
+}
+
+pub struct Watcher(Arc<PollDemand>);
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.0.watchers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// How the metadata API is polled.
+pub struct PollSettings {
+    /// Between polls, unless the API says when the track ends.
+    pub every: Duration,
+    /// What relative cover URLs are relative to, instead of the address of
+    /// the response they came in.
+    pub art_base: Option<Url>,
+}
+
+/// Polls the metadata API and merges what it says with the titles sent along
+/// with the audio (`stream_titles`), going with whichever changed last.
+/// Switching `source` starts over with nothing playing. `tx` is only sent to
+/// when something changed; `fetched` on every successful poll.
+pub async fn metadata_loop(
+    client: Client,
+    tx: watch::Sender<NowPlaying>,
+    fetched: watch::Sender<Option<Instant>>,
+    mut stream_titles: watch::Receiver<Option<String>>,
+    mut source: watch::Receiver<MetadataSource>,
+    demand: Arc<PollDemand>,
+    settings: PollSettings,
+) -> Result<()> {
+    let mut next_poll = tokio::time::Instant::now();
+    // the API's last answer, and what's being shown
+    let mut polled = NowPlaying::default();
+    let mut current = NowPlaying::default();
+    let mut stream_open = true;
+    let mut source_open = true;
+
+    loop {
+        // Nobody's looking: stream titles and source switches are still
+        // taken in, but polling waits until someone is, then goes straight away.
+        let wanted = demand.wanted();
+        tokio::select! {
+            _ = sleep_until(next_poll), if wanted => {}
+            _ = demand.wake.notified(), if !wanted => {
+                next_poll = tokio::time::Instant::now();
+                continue;
+            }
+            changed = source.changed(), if source_open => {
+                if changed.is_err() {
+                    source_open = false;
                     continue;
                 }
-                if let Ok(json) = resp.json::<Value>().await {
-                    if let Some(np) = parse_possible_metadata(&json) {
-                        let _ = tx.send(np);
-                        break;
-                    }
+                source.borrow_and_update();
+                polled = NowPlaying::default();
+                current = NowPlaying::default();
+                publish(&tx, &current);
+                next_poll = tokio::time::Instant::now();
+                continue;
+            }
+            changed = stream_titles.changed(), if stream_open => {
+                if changed.is_err() {
+                    stream_open = false;
+                    continue;
                 }
+                let Some(title) = stream_titles.borrow_and_update().clone() else {
+                    continue;
+                };
+                let mut np = NowPlaying::from_stream_title(title);
+                // the API may still be on the previous track, and its cover with it
+                if !np.same_track(&polled) {
+                    // the track changed sooner than the API said it would
+                    let soon = tokio::time::Instant::now() + settings.every;
+                    next_poll = next_poll.min(soon);
+                } else {
+                    np.art_url = polled.art_url.clone();
+                    np.album = polled.album.clone();
+                    np.genre = polled.genre.clone();
+                    np.kind = polled.kind.clone();
+                    np.progress = polled.progress;
+                }
+                current = np;
+                publish(&tx, &current);
+                continue;
             }
         }
+
+        let from = source.borrow().clone();
+        next_poll = tokio::time::Instant::now() + settings.every;
+        let Some(np) = fetch_now_playing(&client, &from, settings.art_base.as_ref()).await else {
+            continue;
+        };
+        next_poll = tokio::time::Instant::now() + poll_delay(&np, settings.every);
+        let _ = fetched.send(Some(Instant::now()));
+        if np != polled {
+            polled = np;
+            current = polled.clone();
+        }
+        publish(&tx, &current);
     }
 }
 
+/// Sends `np` unless it's what's there already, so receivers only wake for
+/// changes. Returns whether it was sent.
+fn publish(tx: &watch::Sender<NowPlaying>, np: &NowPlaying) -> bool {
+    tx.send_if_modified(|current| {
+        if current == np {
+            return false;
+        }
+        *current = np.clone();
+        true
+    })
+}
+
+/// Once a track should be over, the next poll waits this long for the API
+/// to move on, plus up to `POLL_JITTER` so clients don't all ask at once.
+const POLL_AFTER_END: Duration = Duration::from_secs(2);
+const POLL_JITTER: Duration = Duration::from_secs(1);
+/// The longest wait between polls, in case a track's length is off.
+const POLL_WAIT_MAX: Duration = Duration::from_secs(600);
+
+/// How long to wait before asking again: until just after `np` should end,
+/// when the API said how long it is, otherwise `every`.
+fn poll_delay(np: &NowPlaying, every: Duration) -> Duration {
+    let Some(progress) = np.progress else {
+        return every;
+    };
+    let left = progress.duration.saturating_sub(progress.elapsed_now());
+    if left.is_zero() {
+        // it's over and the API hasn't caught up yet
+        return every;
+    }
+    let jitter = POLL_JITTER.mul_f32(rand::thread_rng().gen::<f32>());
+    left.saturating_add(POLL_AFTER_END)
+        .saturating_add(jitter)
+        .min(POLL_WAIT_MAX)
+}
+
+const PLAZA_PRIMARY: &str = "https://api.plaza.one/radio/broadcast";
+const PLAZA_FALLBACKS: [&str; 3] = [
+    "https://api.plaza.one/status",
+    "https://api.plaza.one/now_playing",
+    "http://radio.plaza.one/status-json.xsl",
+];
+
+/// How long one metadata request may take, well short of the client's own
+/// timeout, so an endpoint that hangs gives way to the fallbacks in the same
+/// tick.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Asks `source` what's playing, once; `metadata_loop` does this every tick.
+pub async fn fetch_now_playing(
+    client: &Client,
+    source: &MetadataSource,
+    art_base: Option<&Url>,
+) -> Option<NowPlaying> {
+    match source {
+        MetadataSource::Plaza => {
+            poll_plaza(
+                client,
+                PLAZA_PRIMARY,
+                &PLAZA_FALLBACKS,
+                art_base,
+                REQUEST_TIMEOUT,
+            )
+            .await
+        }
+        MetadataSource::Url(url) => {
+            let np = parse_plaza_api(&fetch_json(client, url, REQUEST_TIMEOUT).await?)?;
+            Some(resolve_art(np, url, art_base))
+        }
+        MetadataSource::StreamOnly => None,
+    }
+}
+
+/// Asks `primary`, then each of `fallbacks` until one makes sense, giving
+/// each request up to `limit`.
+async fn poll_plaza(
+    client: &Client,
+    primary: &str,
+    fallbacks: &[&str],
+    art_base: Option<&Url>,
+    limit: Duration,
+) -> Option<NowPlaying> {
+    if let Some(json) = fetch_json(client, primary, limit).await {
+        if let Some(np) = parse_plaza_api(&json) {
+            return Some(resolve_art(np, primary, art_base));
+        }
+    }
+
+    for &url in fallbacks {
+        if let Some(json) = fetch_json(client, url, limit).await {
+            if let Some(np) = parse_possible_metadata(&json) {
+                return Some(resolve_art(np, url, art_base));
+            }
+        }
+    }
+    None
+}
+
+/// Makes a relative cover URL absolute, against `art_base` if set and
+/// otherwise the URL of the response it came in (`from`). One that can't be
+/// made sense of is dropped.
+fn resolve_art(mut np: NowPlaying, from: &str, art_base: Option<&Url>) -> NowPlaying {
+    let base = match art_base {
+        Some(base) => Some(base.clone()),
+        None => Url::parse(from).ok(),
+    };
+    np.art_url = np.art_url.and_then(|art| match &base {
+        Some(base) => base.join(art.trim()).ok().map(String::from),
+        // nothing to resolve against; only an absolute one will do
+        None => Url::parse(art.trim()).ok().map(String::from),
+    });
+    np
+}
+
+/// GETs `url` and parses the body as JSON. Unreachable endpoints and error
+/// statuses are skipped quietly; one that takes longer than `limit`, or a
+/// body that isn't valid JSON, is logged.
+async fn fetch_json(client: &Client, url: &str, limit: Duration) -> Option<Value> {
+    let body = match timeout(limit, fetch_body(client, url)).await {
+        Ok(body) => body?,
+        Err(_) => {
+            warn!(url, ?limit, "metadata request timed out");
+            return None;
+        }
+    };
+
+    match serde_json::from_str(&body) {
+        Ok(json) => Some(json),
+        Err(e) => {
+            warn!(url, error = %e, body = snippet(&body), "invalid metadata JSON");
+            None
+        }
+    }
+}
+
+async fn fetch_body(client: &Client, url: &str) -> Option<String> {
+    let resp = client.get(url).send().await.ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+
+    match resp.text().await {
+        Ok(body) => Some(body),
+        Err(e) => {
+            warn!(url, error = %e, "failed to read metadata response");
+            None
+        }
+    }
+}
+
+fn snippet(body: &str) -> String {
+    const MAX_CHARS: usize = 120;
+    let mut s: String = body.chars().take(MAX_CHARS).collect();
+    if body.chars().count() > MAX_CHARS {
+        s.push('…');
+    }
+    s
+}
+
 fn parse_plaza_api(v: &Value) -> Option<NowPlaying> {
     if let Some(np) = v.get("now_playing") {
         return extract_song_info(np);
@@ -87,35 +443,104 @@ fn extract_song_info(v: &Value) -> Option<NowPlaying> {
     let art_url = v
         .get("artwork")
         .or_else(|| v.get("artwork_url"))
+        .or_else(|| v.get("artwork_src"))
         .or_else(|| v.get("art"))
         .or_else(|| v.get("cover"))
         .or_else(|| v.get("cover_url"))
         .or_else(|| v.get("image"))
         .or_else(|| v.get("album_art"))
         .and_then(|x| x.as_str())
-        .map(|s| {
-            if s.starts_with("http://") || s.starts_with("https://") {
-                s.to_string()
-            } else if s.starts_with("//") {
-                format!("https:{}", s)
-            } else if s.starts_with("/") {
-                format!("https://api.plaza.one{}", s)
-            } else {
-                format!("https://api.plaza.one/{}", s)
-            }
-        });
+        .map(|s| s.to_string());
+
+    // some responses leave out the artist and put "Artist - Title" in the title
+    let (artist, title, raw_title) = match (artist, title) {
+        (None, Some(title)) => {
+            let split = NowPlaying::from_stream_title(title);
+            (split.artist, split.title, split.raw_title)
+        }
+        (artist, title) => (artist, title, None),
+    };
 
     if artist.is_some() || title.is_some() {
         Some(NowPlaying {
             artist,
             title,
             art_url,
+            raw_title,
+            album: album(v),
+            genre: genre(v),
+            kind: kind(v),
+            progress: progress(v),
         })
     } else {
         None
     }
 }
 
+/// `album` or `release`, when it's a non-empty string.
+fn album(v: &Value) -> Option<String> {
+    v.get("album")
+        .or_else(|| v.get("release"))
+        .and_then(|x| x.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+/// `genre`, or `tags` as a string or a list of them joined with commas.
+fn genre(v: &Value) -> Option<String> {
+    let genre = match v.get("genre").or_else(|| v.get("tags"))? {
+        Value::String(genre) => genre.trim().to_string(),
+        Value::Array(tags) => tags
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => return None,
+    };
+    Some(genre).filter(|genre| !genre.is_empty())
+}
+
+/// `type` or `kind`, lowercased.
+fn kind(v: &Value) -> Option<String> {
+    v.get("type")
+        .or_else(|| v.get("kind"))
+        .and_then(|x| x.as_str())
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+}
+
+/// No track is longer than this; a length past it is a broken API.
+const TRACK_LENGTH_MAX: Duration = Duration::from_secs(24 * 3600);
+
+/// The track's length in seconds (`duration` or `length`) and either how far
+/// in it is (`elapsed` or `position`) or when it started (`started_at`, Unix
+/// seconds). Without a plausible length there's nothing to show.
+fn progress(v: &Value) -> Option<Progress> {
+    let seconds = |key: &str| v.get(key).and_then(Value::as_f64).filter(|s| *s >= 0.0);
+
+    let duration = seconds("duration").or_else(|| seconds("length"))?;
+    let elapsed = match seconds("elapsed").or_else(|| seconds("position")) {
+        Some(elapsed) => elapsed,
+        None => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+            (now.as_secs_f64() - seconds("started_at")?).max(0.0)
+        }
+    };
+    let duration = Duration::try_from_secs_f64(duration)
+        .ok()
+        .filter(|d| !d.is_zero() && *d <= TRACK_LENGTH_MAX)?;
+    Some(Progress {
+        duration,
+        elapsed: Duration::try_from_secs_f64(elapsed)
+            .unwrap_or(duration)
+            .min(duration),
+        at: Instant::now(),
+    })
+}
+
 fn parse_possible_metadata(v: &Value) -> Option<NowPlaying> {
     if v.is_object() {
         let artist = v
@@ -138,10 +563,19 @@ fn parse_possible_metadata(v: &Value) -> Option<NowPlaying> {
                 artist,
                 title,
                 art_url: image,
+                raw_title: None,
+                album: album(v),
+                genre: genre(v),
+                kind: kind(v),
+                progress: progress(v),
             });
         }
 
-        if let Some(cur) = v.get("current").or_else(|| v.get("now_playing")) {
+        if let Some(cur) = v
+            .get("current")
+            .or_else(|| v.get("now_playing"))
+            .or_else(|| v.get("song"))
+        {
             return extract_song_info(cur);
         }
 
@@ -160,22 +594,622 @@ fn parse_possible_metadata(v: &Value) -> Option<NowPlaying> {
                     .map(|s| s.to_string());
 
                 if let Some(single) = title {
-                    if let Some((artist, t)) = single.split_once(" - ") {
-                        return Some(NowPlaying {
-                            artist: Some(artist.trim().to_string()),
-                            title: Some(t.trim().to_string()),
-                            art_url: None,
-                        });
-                    } else {
-                        return Some(NowPlaying {
-                            artist: None,
-                            title: Some(single),
-                            art_url: None,
-                        });
-                    }
+                    return Some(NowPlaying::from_stream_title(single));
                 }
             }
         }
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn np(artist: Option<&str>, title: Option<&str>, art_url: Option<&str>) -> NowPlaying {
+        NowPlaying {
+            artist: artist.map(str::to_string),
+            title: title.map(str::to_string),
+            art_url: art_url.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plaza_now_playing() {
+        let v = json!({
+            "now_playing": {
+                "artist": "Macross 82-99",
+                "title": "Sailor Mercury",
+                "artwork": "https://cdn.plaza.one/covers/1.jpg"
+            }
+        });
+        assert_eq!(
+            parse_plaza_api(&v),
+            Some(np(
+                Some("Macross 82-99"),
+                Some("Sailor Mercury"),
+                Some("https://cdn.plaza.one/covers/1.jpg")
+            ))
+        );
+    }
+
+    #[test]
+    fn identical_polls_send_once() {
+        let (tx, mut rx) = watch::channel(NowPlaying::default());
+        let response = json!({
+            "now_playing": { "artist": "Desired", "title": "Hold Me" }
+        });
+
+        let mut sends = 0;
+        for _ in 0..2 {
+            let np = parse_plaza_api(&response).unwrap();
+            if publish(&tx, &np) {
+                sends += 1;
+            }
+        }
+        assert_eq!(sends, 1);
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(rx.borrow_and_update().title.as_deref(), Some("Hold Me"));
+        assert!(!rx.has_changed().unwrap());
+    }
+
+    #[test]
+    fn nested_broadcast_now_playing() {
+        let v = json!({
+            "broadcast": {
+                "now_playing": {
+                    "artist": "Yung Bae",
+                    "song": "Bae Signal",
+                    "artwork_url": "//cdn.plaza.one/covers/2.jpg"
+                }
+            }
+        });
+        assert_eq!(
+            parse_plaza_api(&v),
+            Some(np(
+                Some("Yung Bae"),
+                Some("Bae Signal"),
+                Some("//cdn.plaza.one/covers/2.jpg")
+            ))
+        );
+    }
+
+    #[test]
+    fn current_song() {
+        let v = json!({
+            "current_song": {
+                "artist": "Saint Pepsi",
+                "track": "Enjoy Yourself",
+                "cover": "/covers/3.png"
+            }
+        });
+        assert_eq!(
+            parse_plaza_api(&v),
+            Some(np(
+                Some("Saint Pepsi"),
+                Some("Enjoy Yourself"),
+                Some("/covers/3.png")
+            ))
+        );
+    }
+
+    #[test]
+    fn art_is_resolved_against_the_response() {
+        let art = |art: &str, from: &str, base: Option<&str>| {
+            let base = base.map(|b| Url::parse(b).unwrap());
+            resolve_art(np(None, Some("T"), Some(art)), from, base.as_ref()).art_url
+        };
+        let from = "https://api.plaza.one/radio/broadcast";
+        let owned = |url: &str| Some(url.to_string());
+
+        assert_eq!(
+            art("https://cdn.plaza.one/a.jpg", from, None),
+            owned("https://cdn.plaza.one/a.jpg")
+        );
+        assert_eq!(
+            art("//cdn.plaza.one/a.jpg", from, None),
+            owned("https://cdn.plaza.one/a.jpg")
+        );
+        assert_eq!(
+            art("/covers/a.jpg", from, None),
+            owned("https://api.plaza.one/covers/a.jpg")
+        );
+        assert_eq!(
+            art("cover.jpg", from, None),
+            owned("https://api.plaza.one/radio/cover.jpg")
+        );
+        assert_eq!(
+            art("cover.jpg", "http://radio.plaza.one/status-json.xsl", None),
+            owned("http://radio.plaza.one/cover.jpg")
+        );
+
+        // a configured base wins over where the response came from
+        let base = Some("https://cdn.plaza.one/covers/");
+        assert_eq!(
+            art("cover.jpg", from, base),
+            owned("https://cdn.plaza.one/covers/cover.jpg")
+        );
+        assert_eq!(
+            art("/a.jpg", from, base),
+            owned("https://cdn.plaza.one/a.jpg")
+        );
+        assert_eq!(
+            art("https://elsewhere.example/a.jpg", from, base),
+            owned("https://elsewhere.example/a.jpg")
+        );
+
+        // nothing to go on but the art itself
+        assert_eq!(art("cover.jpg", "not a url", None), None);
+    }
+
+    #[test]
+    fn combined_title_is_split_when_artist_is_missing() {
+        let parts = |v: Value| {
+            let np = extract_song_info(&v).unwrap();
+            (np.artist, np.title)
+        };
+        let owned = |artist: Option<&str>, title: &str| {
+            (artist.map(str::to_string), Some(title.to_string()))
+        };
+
+        assert_eq!(
+            parts(json!({ "title": "Vektroid - Shader" })),
+            owned(Some("Vektroid"), "Shader")
+        );
+        // only the first dash splits
+        assert_eq!(
+            parts(json!({ "artist": null, "title": "Blank Banshee - B:/ Start Up - Remix" })),
+            owned(Some("Blank Banshee"), "B:/ Start Up - Remix")
+        );
+        assert_eq!(
+            parts(json!({ "song": "  Hong Kong Express  -  Dream Heaven " })),
+            owned(Some("Hong Kong Express"), "Dream Heaven")
+        );
+        assert_eq!(
+            parts(json!({ "title": " Station ID " })),
+            owned(None, "Station ID")
+        );
+        assert_eq!(
+            parts(json!({ "title": " - Outro" })),
+            owned(None, "- Outro")
+        );
+        // a real artist leaves the title alone
+        assert_eq!(
+            parts(json!({ "artist": "Luxury Elite", "title": "S.W.A.K. - Live" })),
+            owned(Some("Luxury Elite"), "S.W.A.K. - Live")
+        );
+    }
+
+    #[test]
+    fn art_key_precedence() {
+        let v = json!({
+            "title": "T",
+            "image": "https://example.com/image.jpg",
+            "artwork": "https://example.com/artwork.jpg"
+        });
+        assert_eq!(
+            extract_song_info(&v).and_then(|np| np.art_url),
+            Some("https://example.com/artwork.jpg".to_string())
+        );
+    }
+
+    #[test]
+    fn top_level_now_playing_wins_over_broadcast() {
+        let v = json!({
+            "now_playing": { "artist": "First", "title": "One" },
+            "broadcast": { "now_playing": { "artist": "Second", "title": "Two" } }
+        });
+        assert_eq!(
+            parse_plaza_api(&v),
+            Some(np(Some("First"), Some("One"), None))
+        );
+    }
+
+    #[test]
+    fn empty_now_playing_does_not_fall_through() {
+        let v = json!({ "now_playing": {}, "artist": "Ignored", "title": "Ignored" });
+        assert_eq!(parse_plaza_api(&v), None);
+    }
+
+    #[test]
+    fn generic_object_keeps_art_as_is() {
+        let v = json!({ "artist": "A", "title": "T", "image": "covers/5.png" });
+        let expected = np(Some("A"), Some("T"), Some("covers/5.png"));
+        assert_eq!(parse_possible_metadata(&v), Some(expected.clone()));
+        assert_eq!(parse_plaza_api(&v), Some(expected));
+    }
+
+    #[test]
+    fn generic_object_with_only_art() {
+        let v = json!({ "art": "https://example.com/a.jpg" });
+        assert_eq!(
+            parse_possible_metadata(&v),
+            Some(np(None, None, Some("https://example.com/a.jpg")))
+        );
+    }
+
+    #[test]
+    fn generic_current_object() {
+        let v = json!({ "current": { "artist": "A", "song": "S", "cover_url": "/c.jpg" } });
+        assert_eq!(
+            parse_possible_metadata(&v),
+            Some(np(Some("A"), Some("S"), Some("/c.jpg")))
+        );
+    }
+
+    #[test]
+    fn progress_from_position_or_start_time() {
+        let v = json!({ "artist": "A", "title": "B", "length": 240, "position": 30 });
+        let progress = parse_possible_metadata(&v).unwrap().progress.unwrap();
+        assert_eq!(progress.duration, Duration::from_secs(240));
+        assert_eq!(progress.elapsed, Duration::from_secs(30));
+
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - 60;
+        let v = json!({
+            "now_playing": { "artist": "A", "title": "B", "duration": 200, "started_at": started }
+        });
+        let progress = parse_plaza_api(&v).unwrap().progress.unwrap();
+        assert!(progress.elapsed >= Duration::from_secs(59));
+        assert!(progress.elapsed <= Duration::from_secs(61));
+
+        // no length, no progress
+        let v = json!({ "artist": "A", "title": "B", "position": 30 });
+        assert_eq!(parse_possible_metadata(&v).unwrap().progress, None);
+
+        // nor from a length no track has
+        let v = json!({ "artist": "A", "title": "B", "length": 1e300, "position": 30 });
+        assert_eq!(parse_possible_metadata(&v).unwrap().progress, None);
+        let v = json!({ "artist": "A", "title": "B", "length": 90000, "position": 30 });
+        assert_eq!(parse_possible_metadata(&v).unwrap().progress, None);
+        let v = json!({ "artist": "A", "title": "B", "length": 240, "position": 1e300 });
+        let progress = parse_possible_metadata(&v).unwrap().progress.unwrap();
+        assert_eq!(progress.elapsed, Duration::from_secs(240));
+    }
+
+    #[test]
+    fn icecast_single_source_is_split() {
+        let v = json!({ "icestats": { "source": { "title": " Artist  -  Some Title " } } });
+        assert_eq!(
+            parse_possible_metadata(&v),
+            Some(NowPlaying {
+                raw_title: Some(" Artist  -  Some Title ".to_string()),
+                ..np(Some("Artist"), Some("Some Title"), None)
+            })
+        );
+    }
+
+    #[test]
+    fn icecast_source_array_uses_first() {
+        let v = json!({
+            "icestats": {
+                "source": [
+                    { "title": "First - One" },
+                    { "title": "Second - Two" }
+                ]
+            }
+        });
+        assert_eq!(
+            parse_possible_metadata(&v),
+            Some(NowPlaying {
+                raw_title: Some("First - One".to_string()),
+                ..np(Some("First"), Some("One"), None)
+            })
+        );
+    }
+
+    #[test]
+    fn icecast_title_without_separator() {
+        let v = json!({ "icestats": { "source": { "title": "Station ID" } } });
+        assert_eq!(
+            parse_possible_metadata(&v),
+            Some(np(None, Some("Station ID"), None))
+        );
+    }
+
+    #[test]
+    fn display_parts_can_swap_stream_titles() {
+        let v = json!({ "icestats": { "source": { "title": "Some Title - Artist" } } });
+        let parsed = parse_possible_metadata(&v).unwrap();
+        assert_eq!(
+            parsed.display_parts(false),
+            (Some("Some Title"), Some("Artist"))
+        );
+        assert_eq!(
+            parsed.display_parts(true),
+            (Some("Artist"), Some("Some Title"))
+        );
+
+        // without a raw stream title there's nothing to reinterpret
+        let plain = np(Some("A"), Some("T"), None);
+        assert_eq!(plain.display_parts(true), (Some("A"), Some("T")));
+    }
+
+    #[test]
+    fn unrecognized_shapes() {
+        assert_eq!(parse_plaza_api(&json!({})), None);
+        assert_eq!(parse_plaza_api(&json!([1, 2, 3])), None);
+        assert_eq!(parse_possible_metadata(&json!("just a string")), None);
+        assert_eq!(extract_song_info(&json!({ "artwork": "x.jpg" })), None);
+    }
+
+    // Hand-written bodies in the shapes `parse_plaza_api` and
+    // `parse_possible_metadata` expect, not captured from the live API.
+
+    fn fixture(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn fixture_broadcast() {
+        let v = fixture(include_str!("../tests/fixtures/metadata/broadcast.json"));
+        let parsed = resolve_art(
+            parse_plaza_api(&v).unwrap(),
+            "https://api.plaza.one/radio/broadcast",
+            None,
+        );
+        assert_eq!(
+            parsed,
+            NowPlaying {
+                progress: Some(Progress {
+                    duration: Duration::from_secs(441),
+                    elapsed: Duration::from_secs(97),
+                    at: Instant::now(),
+                }),
+                album: Some("Floral Shoppe".to_string()),
+                ..np(
+                    Some("Macintosh Plus"),
+                    Some("リサフランク420 / 現代のコンピュー"),
+                    Some("https://api.plaza.one/covers/floral-shoppe.jpg")
+                )
+            }
+        );
+        assert_eq!(parsed.progress.unwrap().elapsed, Duration::from_secs(97));
+    }
+
+    #[test]
+    fn fixture_status_with_the_track_under_song() {
+        let v = fixture(include_str!("../tests/fixtures/metadata/status.json"));
+        let expected = NowPlaying {
+            album: Some("Heaven Express".to_string()),
+            progress: Some(Progress {
+                duration: Duration::from_secs(212),
+                elapsed: Duration::from_secs(41),
+                at: Instant::now(),
+            }),
+            ..np(
+                Some("Hong Kong Express"),
+                Some("Dream Heaven"),
+                Some("https://plaza.one/covers/heaven-express.jpg"),
+            )
+        };
+        let parsed = parse_possible_metadata(&v).unwrap();
+        assert_eq!(parsed, expected);
+        // `Progress` only compares lengths
+        assert_eq!(parsed.progress.unwrap().elapsed, Duration::from_secs(41));
+        assert_eq!(parse_plaza_api(&v), Some(expected));
+    }
+
+    #[test]
+    fn fixture_now_playing_with_protocol_relative_art() {
+        let v = fixture(include_str!("../tests/fixtures/metadata/now_playing.json"));
+        let from = "https://api.plaza.one/now_playing";
+        let expected = NowPlaying {
+            album: Some("Bae 5".to_string()),
+            genre: Some("future funk, disco".to_string()),
+            ..np(
+                Some("Yung Bae"),
+                Some("Bae Signal"),
+                Some("https://cdn.plaza.one/covers/bae-signal.jpg"),
+            )
+        };
+        let parsed = parse_possible_metadata(&v).unwrap();
+        assert_eq!(resolve_art(parsed, from, None), expected);
+        let parsed = parse_plaza_api(&v).unwrap();
+        assert_eq!(resolve_art(parsed, from, None), expected);
+    }
+
+    #[test]
+    fn talk_and_station_ids_are_live() {
+        assert!(np(None, Some("Nightwave Plaza"), None).is_live());
+        assert!(np(Some(""), Some("LIVE with DJ Vektroid"), None).is_live());
+        assert!(np(Some("Nightwave Plaza"), Some("Station ID #3"), None).is_live());
+        // words, not bits of them
+        assert!(!np(None, Some("Deliver Me"), None).is_live());
+        assert!(!np(Some("Plaza Dreams"), Some("Live Forever"), None).is_live());
+        assert!(!np(None, None, None).is_live());
+
+        // the API's word goes
+        let v = fixture(r#"{"artist": "", "title": "Plaza live!", "type": "Music"}"#);
+        assert!(!parse_possible_metadata(&v).unwrap().is_live());
+        let v = fixture(r#"{"artist": "Vektroid", "title": "Good night", "type": "talk"}"#);
+        assert!(parse_possible_metadata(&v).unwrap().is_live());
+    }
+
+    #[test]
+    fn fixture_current_song_with_bare_art_path() {
+        let v = fixture(include_str!("../tests/fixtures/metadata/current_song.json"));
+        let parsed = parse_plaza_api(&v).unwrap();
+        assert_eq!(
+            resolve_art(parsed, "https://api.plaza.one/radio/broadcast", None),
+            np(
+                Some("Luxury Elite"),
+                Some("S.W.A.K."),
+                Some("https://api.plaza.one/radio/covers/world-class.png")
+            )
+        );
+        // only the Plaza parser knows `current_song`
+        assert_eq!(parse_possible_metadata(&v), None);
+    }
+
+    #[test]
+    fn fixture_malformed() {
+        let truncated = include_str!("../tests/fixtures/metadata/malformed.json");
+        assert!(serde_json::from_str::<Value>(truncated).is_err());
+
+        let maintenance = json!({ "error": "maintenance", "retry_after": 60 });
+        assert_eq!(parse_plaza_api(&maintenance), None);
+        assert_eq!(parse_possible_metadata(&maintenance), None);
+    }
+
+    /// Serves `body` as JSON on a local port, except under /slow, which
+    /// doesn't answer for a minute. Returns the address to ask.
+    async fn mock_server(body: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0u8; 1024];
+                    let n = stream.read(&mut request).await.unwrap_or(0);
+                    if request[..n].starts_with(b"GET /slow ") {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                    }
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn next_poll_waits_for_the_end_of_the_track() {
+        let every = Duration::from_secs(5);
+        let mut track = np(Some("a"), Some("b"), None);
+        assert_eq!(poll_delay(&track, every), every);
+
+        track.progress = Some(Progress {
+            duration: Duration::from_secs(360),
+            elapsed: Duration::from_secs(60),
+            at: Instant::now(),
+        });
+        let delay = poll_delay(&track, every);
+        assert!(delay > Duration::from_secs(300) + POLL_AFTER_END - Duration::from_millis(100));
+        assert!(delay <= Duration::from_secs(300) + POLL_AFTER_END + POLL_JITTER);
+
+        // the API is still on a track that should be over
+        track.progress = Some(Progress {
+            duration: Duration::from_secs(360),
+            elapsed: Duration::from_secs(360),
+            at: Instant::now(),
+        });
+        assert_eq!(poll_delay(&track, every), every);
+
+        track.progress = Some(Progress {
+            duration: Duration::from_secs(3 * 3600),
+            elapsed: Duration::ZERO,
+            at: Instant::now(),
+        });
+        assert_eq!(poll_delay(&track, every), POLL_WAIT_MAX);
+
+        track.progress = Some(Progress {
+            duration: Duration::MAX,
+            elapsed: Duration::ZERO,
+            at: Instant::now(),
+        });
+        assert_eq!(poll_delay(&track, every), POLL_WAIT_MAX);
+    }
+
+    #[tokio::test]
+    async fn slow_primary_gives_way_to_the_fallbacks() {
+        let base = mock_server(include_str!("../tests/fixtures/metadata/now_playing.json")).await;
+        let client = Client::builder().no_proxy().build().unwrap();
+        let primary = format!("{}/slow", base);
+        let fallback = format!("{}/now_playing", base);
+
+        let started = Instant::now();
+        let polled = poll_plaza(
+            &client,
+            &primary,
+            &[&fallback],
+            None,
+            Duration::from_millis(200),
+        )
+        .await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let polled = polled.unwrap();
+        assert_eq!(polled.artist.as_deref(), Some("Yung Bae"));
+        assert_eq!(polled.title.as_deref(), Some("Bae Signal"));
+    }
+
+    #[tokio::test]
+    async fn without_stream_titles_the_track_comes_from_polling() {
+        let base = mock_server(include_str!("../tests/fixtures/metadata/now_playing.json")).await;
+        let (tx, mut rx) = watch::channel(NowPlaying::default());
+        let (fetched, _) = watch::channel(None);
+        // no audio, so nothing will ever send a title
+        let (_, titles) = watch::channel(None);
+        let (_source_tx, source) =
+            watch::channel(MetadataSource::Url(format!("{}/now_playing", base)));
+        let demand = Arc::new(PollDemand::default());
+        let settings = PollSettings {
+            every: Duration::from_secs(5),
+            art_base: None,
+        };
+        tokio::spawn(metadata_loop(
+            Client::builder().no_proxy().build().unwrap(),
+            tx,
+            fetched,
+            titles,
+            source,
+            demand.clone(),
+            settings,
+        ));
+
+        // nobody's watching, so nothing's polled
+        assert!(timeout(Duration::from_millis(300), rx.changed())
+            .await
+            .is_err());
+
+        let _watcher = demand.watch();
+        timeout(Duration::from_secs(2), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rx.borrow().title.as_deref(), Some("Bae Signal"));
+    }
+
+    #[tokio::test]
+    async fn stream_titles_come_through_while_nobody_watches() {
+        let (tx, mut rx) = watch::channel(NowPlaying::default());
+        let (fetched, _) = watch::channel(None);
+        let (titles_tx, titles) = watch::channel(None);
+        let (_source_tx, source) = watch::channel(MetadataSource::Plaza);
+        let demand = Arc::new(PollDemand::default());
+        let settings = PollSettings {
+            every: Duration::from_secs(5),
+            art_base: None,
+        };
+        tokio::spawn(metadata_loop(
+            Client::new(),
+            tx,
+            fetched,
+            titles,
+            source,
+            demand,
+            settings,
+        ));
+
+        titles_tx
+            .send(Some("Macross 82-99 - Sailor Mercury".to_string()))
+            .unwrap();
+        timeout(Duration::from_secs(1), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rx.borrow().title.as_deref(), Some("Sailor Mercury"));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::metadata::{NowPlaying, Watcher};
+use tokio::sync::{mpsc, watch};
+
+/// Requests coming in from media keys and desktop widgets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    SetVolume(f32),
+    /// Turns the volume up (or, negative, down) by this much.
+    ChangeVolume(f32),
+}
+
+/// What the desktop gets to see about the player.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    pub track: NowPlaying,
+    pub paused: bool,
+    pub volume: f32,
+}
+
+/// The UI's end of the MPRIS connection.
+pub struct Remote {
+    pub commands: mpsc::UnboundedReceiver<Command>,
+    pub status: watch::Sender<Status>,
+    // for other remotes (the status socket) to share
+    commands_tx: mpsc::UnboundedSender<Command>,
+}
+
+impl Remote {
+    /// Another way in, alongside MPRIS: commands sent here reach the UI just
+    /// the same, and the status follows what MPRIS sees.
+    pub fn connect(&self) -> (mpsc::UnboundedSender<Command>, watch::Receiver<Status>) {
+        (self.commands_tx.clone(), self.status.subscribe())
+    }
+}
+
+/// Registers the player on the session bus. The D-Bus side isn't `Send`, so it
+/// gets a thread of its own; elsewhere than Linux this is a no-op. Desktop
+/// widgets show the track whatever the UI does, so `watcher` keeps metadata
+/// coming for as long as the bus connection lasts.
+pub fn spawn(watcher: Watcher) -> Remote {
+    let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+    let (status_tx, status_rx) = watch::channel(Status::default());
+    let commands = commands_tx.clone();
+
+    #[cfg(target_os = "linux")]
+    std::thread::spawn(move || {
+        let _watcher = watcher;
+        let Ok(rt) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        else {
+            return;
+        };
+        // No session bus (over SSH, say) just means no media keys.
+        let _ = tokio::task::LocalSet::new().block_on(&rt, linux::serve(commands_tx, status_rx));
+    });
+    #[cfg(not(target_os = "linux"))]
+    drop((commands_tx, status_rx, watcher));
+
+    Remote {
+        commands: commands_rx,
+        status: status_tx,
+        commands_tx: commands,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{Command, Status};
+    use anyhow::Result;
+    use mpris_server::{Metadata, PlaybackStatus, Player};
+    use tokio::sync::{mpsc, watch};
+
+    pub async fn serve(
+        commands: mpsc::UnboundedSender<Command>,
+        mut status: watch::Receiver<Status>,
+    ) -> Result<()> {
+        let player = Player::builder("plaza_tui")
+            .identity("Plaza Radio")
+            .can_play(true)
+            .can_pause(true)
+            .can_control(true)
+            .build()
+            .await?;
+
+        let tx = commands.clone();
+        player.connect_play(move |_| {
+            let _ = tx.send(Command::Play);
+        });
+        let tx = commands.clone();
+        player.connect_pause(move |_| {
+            let _ = tx.send(Command::Pause);
+        });
+        let tx = commands.clone();
+        player.connect_play_pause(move |_| {
+            let _ = tx.send(Command::PlayPause);
+        });
+        let tx = commands.clone();
+        player.connect_stop(move |_| {
+            let _ = tx.send(Command::Stop);
+        });
+        player.connect_set_volume(move |_, volume| {
+            let _ = commands.send(Command::SetVolume(volume as f32));
+        });
+
+        tokio::task::spawn_local(player.run());
+
+        let mut last = Status::default();
+        while status.changed().await.is_ok() {
+            let current = status.borrow_and_update().clone();
+
+            if current.paused != last.paused || last == Status::default() {
+                player
+                    .set_playback_status(if current.paused {
+                        PlaybackStatus::Paused
+                    } else {
+                        PlaybackStatus::Playing
+                    })
+                    .await?;
+            }
+            if current.volume != last.volume {
+                player.set_volume(current.volume as f64).await?;
+            }
+            if current.track != last.track {
+                let mut metadata = Metadata::builder();
+                if let Some(title) = &current.track.title {
+                    metadata = metadata.title(title);
+                }
+                if let Some(artist) = &current.track.artist {
+                    metadata = metadata.artist([artist]);
+                }
+                if let Some(art) = &current.track.art_url {
+                    metadata = metadata.art_url(art);
+                }
+                player.set_metadata(metadata.build()).await?;
+            }
+
+            last = current;
+        }
+
+        Ok(())
+    }
+}
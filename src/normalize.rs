@@ -0,0 +1,175 @@
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Loudness is measured over blocks of this many frames.
+const BLOCK_FRAMES: u32 = 1024;
+// How long the loudness estimate takes to settle on a new track, and how
+// long the gain takes to follow it. Slow enough not to pump with the beat.
+const LOUDNESS_SECS: f32 = 3.0;
+const GAIN_SECS: f32 = 1.5;
+// The gain stays within this many dB either way.
+const MAX_GAIN_DB: f32 = 12.0;
+// Blocks quieter than this (gaps, fades) don't count towards the loudness.
+const SILENCE_DB: f32 = -50.0;
+// Boosted samples past this start bending so they never go past 1.0.
+const LIMIT_KNEE: f32 = 0.9;
+
+/// Nudges `inner` towards `target_db` RMS (close enough to LUFS for music)
+/// while `enabled` is set, and eases back to unity gain while it isn't.
+pub struct Normalized<S> {
+    inner: S,
+    enabled: Arc<AtomicBool>,
+    // as mean squares
+    target: f32,
+    loudness: Option<f32>,
+    block_sum: f32,
+    block_len: u32,
+    block_size: u32,
+    gain: f32,
+    // added to `gain` every sample, so it glides to the next block's value
+    step: f32,
+    loudness_weight: f32,
+    gain_weight: f32,
+}
+
+impl<S: Source<Item = f32>> Normalized<S> {
+    pub fn new(inner: S, enabled: Arc<AtomicBool>, target_db: f32) -> Self {
+        let block_secs = BLOCK_FRAMES as f32 / inner.sample_rate().max(1) as f32;
+        Normalized {
+            block_size: BLOCK_FRAMES * inner.channels().max(1) as u32,
+            inner,
+            enabled,
+            target: db_to_power(target_db),
+            loudness: None,
+            block_sum: 0.0,
+            block_len: 0,
+            gain: 1.0,
+            step: 0.0,
+            loudness_weight: 1.0 - (-block_secs / LOUDNESS_SECS).exp(),
+            gain_weight: 1.0 - (-block_secs / GAIN_SECS).exp(),
+        }
+    }
+
+    /// Folds the finished block into the loudness estimate and sets the gain
+    /// gliding towards where it should be.
+    fn end_block(&mut self) {
+        let mean_square = self.block_sum / self.block_len as f32;
+        self.block_sum = 0.0;
+        self.block_len = 0;
+
+        if mean_square > db_to_power(SILENCE_DB) {
+            self.loudness = Some(match self.loudness {
+                Some(loudness) => loudness + (mean_square - loudness) * self.loudness_weight,
+                None => mean_square,
+            });
+        }
+
+        let max_gain = db_to_amplitude(MAX_GAIN_DB);
+        let wanted = match self.loudness {
+            Some(loudness) if self.enabled.load(Ordering::Relaxed) => (self.target / loudness)
+                .sqrt()
+                .clamp(1.0 / max_gain, max_gain),
+            _ => 1.0,
+        };
+        let next = self.gain + (wanted - self.gain) * self.gain_weight;
+        self.step = (next - self.gain) / self.block_size as f32;
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Normalized<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        self.block_sum += sample * sample;
+        self.block_len += 1;
+        if self.block_len == self.block_size {
+            self.end_block();
+        }
+
+        self.gain += self.step;
+        let out = sample * self.gain;
+        Some(if self.gain > 1.0 { limit(out) } else { out })
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Normalized<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Leaves samples under the knee alone and squeezes the rest into what's
+/// left up to 1.0.
+fn limit(sample: f32) -> f32 {
+    let level = sample.abs();
+    if level <= LIMIT_KNEE {
+        return sample;
+    }
+    let room = 1.0 - LIMIT_KNEE;
+    sample.signum() * (LIMIT_KNEE + room * ((level - LIMIT_KNEE) / room).tanh())
+}
+
+fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+fn db_to_power(db: f32) -> f32 {
+    10f32.powf(db / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        10.0 * mean_square.log10()
+    }
+
+    #[test]
+    fn quiet_audio_is_raised_towards_the_target() {
+        // a sine at 0.05 sits around -29 dB RMS
+        let quiet = SineWave::new(440.0).amplify(0.05);
+        let rate = quiet.sample_rate() as usize;
+        let samples: Vec<f32> = Normalized::new(quiet, Arc::new(AtomicBool::new(true)), -20.0)
+            .take(rate * 20)
+            .collect();
+
+        let settled = rms_db(&samples[samples.len() - rate..]);
+        assert!((settled + 20.0).abs() < 1.0, "settled at {} dB", settled);
+    }
+
+    #[test]
+    fn disabled_passes_samples_through() {
+        let quiet = SineWave::new(440.0).amplify(0.05);
+        let expected: Vec<f32> = quiet.clone().take(100_000).collect();
+        let samples: Vec<f32> = Normalized::new(quiet, Arc::new(AtomicBool::new(false)), -20.0)
+            .take(100_000)
+            .collect();
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn limiter_keeps_peaks_under_full_scale() {
+        assert_eq!(limit(0.5), 0.5);
+        assert_eq!(limit(-0.9), -0.9);
+        for peak in [0.95, 1.0, 2.0, 8.0] {
+            assert!(limit(peak) <= 1.0);
+            assert!(limit(-peak) >= -1.0);
+        }
+        assert!(limit(1.0) > limit(0.95));
+    }
+}
@@ -1,10 +1,23 @@
-use anyhow::{anyhow, Result};
-use rodio::{OutputStream, Sink, Source};
+use crate::bus::{Chunk, Feed, Incoming, SampleBus};
+use crate::config::{Backend, Config, Http};
+use crate::decoder;
+use crate::eq::{self, EqGains, Equalized};
+use crate::icy::{self, IcyReader};
+use crate::normalize::Normalized;
+use crate::recorder::Recorder;
+use anyhow::{anyhow, bail, Result};
+use rodio::cpal::traits::HostTrait;
+use rodio::{DeviceTrait, OutputStream, OutputStreamHandle, PlayError, Sink, Source};
 use std::collections::VecDeque;
-use std::io::{BufReader, Read};
-use std::process::{Child, Command, Stdio};
-use std::sync::{mpsc, Arc, Mutex};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStderr, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
 
 const STREAM_CANDIDATES: &[&str] = &[
     "http://radio.plaza.one/mp3",
@@ -12,50 +25,631 @@ const STREAM_CANDIDATES: &[&str] = &[
     "http://radio.plaza.one/opus",
 ];
 
+const RAMP_STEP: Duration = Duration::from_millis(20);
+
+// How long a stream gets to produce audio at startup before the next format
+// is tried, or startup gives up.
+const FIRST_AUDIO_TIMEOUT: Duration = Duration::from_secs(8);
+
+// How long a crossfade waits for the new station's audio before fading the
+// old one out regardless.
+const CROSSFADE_WAIT_MAX: Duration = Duration::from_secs(8);
+
+// Waits between restarts double from the first up to the last.
+const BACKOFF_FIRST: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// What's kept when catching up to live, so playback doesn't run dry straight
+// away.
+const LIVE_CUSHION: Duration = Duration::from_millis(200);
+
+// Samples this quiet (about -72 dBFS) count as silence towards
+// `idle_timeout_secs`.
+const SILENCE_LEVEL: u16 = 8;
+
+// Mono samples kept for the visualizer, and how many are copied over at a time.
+const RECENT_SAMPLES: usize = 2048;
+const TAP_BATCH: usize = 256;
+
+// Frames per VU measurement, and how quickly the meter follows: a VU meter
+// takes about 300 ms to settle on a new level.
+const VU_BLOCK: u32 = 1024;
+const VU_SETTLE_SECS: f32 = 0.3;
+// How fast a peak falls back once the audio is quieter.
+const PEAK_FALL_DB_PER_SEC: f32 = 20.0;
+
+/// The most recently played audio, downmixed to mono.
+pub type RecentSamples = Arc<Mutex<VecDeque<f32>>>;
+
+/// Why the last attempt at the stream didn't get any audio, until one does.
+type LastError = Arc<Mutex<Option<String>>>;
+
+fn record(last_error: &LastError, error: impl std::fmt::Display) {
+    if let Ok(mut last) = last_error.lock() {
+        *last = Some(error.to_string());
+    }
+}
+
+/// Smoothed RMS of the left and right channels and their slowly falling
+/// sample peaks, before the volume is applied. Stored as `f32` bits so the
+/// audio thread never waits on a lock.
+#[derive(Default)]
+pub struct Levels {
+    left: AtomicU32,
+    right: AtomicU32,
+    peaks: [AtomicU32; 2],
+    // the loudest sample since `take_loudest` last looked
+    loudest: AtomicU32,
+}
+
+impl Levels {
+    pub fn get(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.left.load(Ordering::Relaxed)),
+            f32::from_bits(self.right.load(Ordering::Relaxed)),
+        )
+    }
+
+    pub fn peaks(&self) -> (f32, f32) {
+        let [left, right] = &self.peaks;
+        (
+            f32::from_bits(left.load(Ordering::Relaxed)),
+            f32::from_bits(right.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// The loudest sample played since the last call, so none that clipped
+    /// is missed between two looks.
+    pub fn take_loudest(&self) -> f32 {
+        f32::from_bits(self.loudest.swap(0, Ordering::Relaxed))
+    }
+
+    /// Raises each peak to a new block's, or lets it fall by `fall`.
+    fn hold(&self, peaks: [f32; 2], fall: f32) {
+        for (held, peak) in self.peaks.iter().zip(peaks) {
+            let current = f32::from_bits(held.load(Ordering::Relaxed));
+            held.store((current * fall).max(peak).to_bits(), Ordering::Relaxed);
+        }
+        let loudest = peaks[0].max(peaks[1]);
+        // positive floats order the same as their bits
+        self.loudest.fetch_max(loudest.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Moves both levels `weight` of the way towards a new measurement.
+    fn follow(&self, left: f32, right: f32, weight: f32) {
+        for (level, rms) in [(&self.left, left), (&self.right, right)] {
+            let current = f32::from_bits(level.load(Ordering::Relaxed));
+            level.store(
+                (current + (rms - current) * weight).to_bits(),
+                Ordering::Relaxed,
+            );
+        }
+    }
+}
+
 pub struct SinkInfo {
     pub _channels: u16,
     pub _sample_rate: u32,
+    pub recent: RecentSamples,
+    pub levels: Arc<Levels>,
+    pub stream_info: Arc<Mutex<Option<StreamInfo>>>,
+}
+
+/// The stream's format, as ffmpeg reports it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub codec: String,
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: Option<u32>,
+    /// The channel layout, e.g. "stereo".
+    pub channels: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Waiting on the first audio since startup.
+    Connecting,
+    Connected,
+    Reconnecting {
+        attempt: u32,
+    },
+    /// Gave up after too many failed restarts; waiting for `retry()`.
+    Failed,
+}
+
+enum Signal {
+    Retry,
+    Resume,
+    Stop,
 }
 
 pub struct PlayerControl {
     pub child: Arc<Mutex<Option<Child>>>,
     pub sink: Arc<Mutex<Sink>>,
-    _stream: Arc<OutputStream>, // must keep alive or audio stops
+    url: Arc<Mutex<String>>,
+    backend: Backend,
+    stats: Arc<PlaybackStats>,
+    state: Arc<Mutex<ConnectionState>>,
+    last_error: LastError,
+    // bumped on every volume change so an in-flight ramp knows to give up
+    volume_generation: Arc<AtomicU64>,
+    ducked_from: Mutex<Option<f32>>,
+    muted_from: Mutex<Option<f32>>,
+    stopping: Arc<AtomicBool>,
+    // set while paused with the decoder shut down (`pause_disconnects`)
+    suspended: Arc<AtomicBool>,
+    // set while paused because the stream went silent, until `play`
+    idled: AtomicBool,
+    // set when the decoder is shut down to start over on another stream
+    restart: Arc<AtomicBool>,
+    pause_disconnects: bool,
+    // the most `set_volume` allows, at most 2.0
+    max_volume: f32,
+    // how long to fade in at startup and around pauses
+    fade: Duration,
+    // the volume to come back to while fading out to pause
+    pausing: Arc<Mutex<Option<f32>>>,
+    // how long switching stations crossfades, and whether one is starting
+    crossfade: Duration,
+    crossfading: Arc<AtomicBool>,
+    // decoders playing out under a crossfade, until they're shut down
+    fading: Arc<Mutex<Vec<Child>>>,
+    normalize: Arc<AtomicBool>,
+    mono: Arc<AtomicBool>,
+    eq: Arc<EqGains>,
+    signals: mpsc::Sender<Signal>,
+    bus: Arc<SampleBus>,
+    recorder: Mutex<Option<Recorder>>,
+    output: Box<dyn Output>,
+}
+
+/// Whatever way the player goes away (a panic, an early return, a signal), the
+/// decoder goes with it rather than carrying on downloading.
+impl Drop for PlayerControl {
+    fn drop(&mut self) {
+        if !self.stopping.load(Ordering::SeqCst) {
+            self.stop();
+        }
+    }
+}
+
+/// Kills and waits for the decoder in `child`, if there is one, leaving `None`.
+fn reap(child: &Mutex<Option<Child>>) {
+    if let Ok(mut guard) = child.lock() {
+        if let Some(mut c) = guard.take() {
+            let _ = c.kill();
+            let _ = c.wait();
+        }
+    }
 }
 
 impl PlayerControl {
     pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let _ = self.signals.send(Signal::Stop);
+
         if let Ok(s) = self.sink.lock() {
             s.stop();
         }
 
-        if let Ok(mut guard) = self.child.lock() {
-            if let Some(mut c) = guard.take() {
+        self.kill_child();
+        if let Ok(mut fading) = self.fading.lock() {
+            for mut c in fading.drain(..) {
                 let _ = c.kill();
                 let _ = c.wait();
             }
         }
+
+        self.bus.close();
+        if let Err(e) = self.stop_recording() {
+            warn!("{:#}", e);
+        }
     }
 
+    /// Pauses playback, after fading out if `fade_ms` is set. With
+    /// `pause_disconnects` the decoder is shut down too, so nothing is
+    /// downloaded until `play` reconnects.
     pub fn pause(&self) {
+        if self.fade.is_zero() {
+            if let Ok(s) = self.sink.lock() {
+                s.pause();
+            }
+        } else {
+            // The sink is only locked while `pausing` isn't held: the ramp
+            // takes them the other way round when it finishes.
+            let playing = !self.sink.lock().map(|s| s.is_paused()).unwrap_or(true);
+            let volume = self.volume();
+            let started = playing
+                && match self.pausing.lock() {
+                    // a fade-out already going keeps its volume
+                    Ok(mut p) if p.is_none() => {
+                        *p = Some(volume);
+                        true
+                    }
+                    _ => false,
+                };
+            if started {
+                let pausing = self.pausing.clone();
+                self.ramp(0.0, self.fade, false, move |sink, finished| {
+                    // unless `play` came first
+                    if pausing.lock().ok().and_then(|mut p| p.take()).is_some() {
+                        sink.pause();
+                        // cut short by a volume change, which should stick
+                        if finished {
+                            sink.set_volume(volume);
+                        }
+                    }
+                });
+            }
+        }
+
+        if self.pause_disconnects && !self.suspended.swap(true, Ordering::SeqCst) {
+            self.kill_child();
+        }
+    }
+
+    fn kill_child(&self) {
+        reap(&self.child);
+    }
+
+    /// Pauses and shuts the decoder down if the source has played nothing
+    /// but silence for `idle_timeout_secs`, whatever `pause_disconnects`
+    /// says. Returns whether it did; `play` reconnects.
+    pub fn pause_if_idle(&self) -> bool {
+        if !self.stats.idle.swap(false, Ordering::SeqCst) || self.is_paused() {
+            return false;
+        }
+        warn!("the stream has been silent too long; pausing");
+        self.idled.store(true, Ordering::SeqCst);
+        // silent anyway, so no fade
         if let Ok(s) = self.sink.lock() {
             s.pause();
         }
+        if !self.suspended.swap(true, Ordering::SeqCst) {
+            self.kill_child();
+        }
+        true
     }
 
+    /// Whether the player paused itself over a silent stream.
+    pub fn is_idle(&self) -> bool {
+        self.idled.load(Ordering::SeqCst)
+    }
+
+    /// Switches to another stream and has the supervisor start over on `url`.
+    /// With `crossfade_ms` set the old stream fades out under the new one;
+    /// otherwise what's buffered is dropped and the decoder shut down.
+    pub fn switch_stream(&self, url: &str) {
+        self.set_url(url);
+        self.restart_decoder(true);
+    }
+
+    fn set_url(&self, url: &str) {
+        if let Ok(mut current) = self.url.lock() {
+            *current = url.to_string();
+        }
+    }
+
+    /// Sums the channels into one for single-speaker setups, or goes back to
+    /// stereo. The source's channel count can't change while it plays, so
+    /// the decoder starts over.
+    pub fn toggle_mono(&self) {
+        self.mono.fetch_xor(true, Ordering::SeqCst);
+        self.restart_decoder(false);
+    }
+
+    pub fn is_mono(&self) -> bool {
+        self.mono.load(Ordering::SeqCst)
+    }
+
+    /// Switches through `urls` until one gets audio to the sink before its
+    /// decoder gives up or `FIRST_AUDIO_TIMEOUT` passes, and returns that one.
+    fn first_that_plays(&self, urls: &[String]) -> Result<String, StartError> {
+        for (i, url) in urls.iter().enumerate() {
+            let failed_before = self.stats.failed_runs.load(Ordering::SeqCst);
+            if i > 0 {
+                warn!(url = urls[i - 1], "no audio; trying the next format");
+                self.stats.decoded.store(false, Ordering::SeqCst);
+                self.set_url(url);
+                self.restart_decoder(false);
+            }
+            if self
+                .stats
+                .wait_for_first_audio(failed_before, FIRST_AUDIO_TIMEOUT)
+            {
+                return Ok(url.clone());
+            }
+        }
+        Err(StartError::NoAudio(self.last_error()))
+    }
+
+    /// Drops everything waiting to be played but a moment's worth, so a live
+    /// stream that has drifted behind catches up. The playing source does
+    /// it, next time it's asked for a sample.
+    pub fn flush_to_live(&self) {
+        self.stats.flush.store(true, Ordering::SeqCst);
+    }
+
+    /// The equalizer's gains in dB, one per band in `eq::BANDS`.
+    pub fn eq(&self) -> [f32; 5] {
+        self.eq.get()
+    }
+
+    /// Changes take a moment to glide in, so they don't click.
+    pub fn set_eq(&self, gains: [f32; 5]) {
+        self.eq.set(gains);
+    }
+
+    pub fn set_eq_band(&self, band: usize, db: f32) {
+        self.eq.set_band(band, db);
+    }
+
+    /// Has the supervisor start a new decoder, either crossfading (if asked
+    /// to and `crossfade_ms` is set) or dropping what's buffered.
+    fn restart_decoder(&self, crossfade: bool) {
+        // disconnected by a pause; resuming starts a new one anyway
+        if self.suspended.load(Ordering::SeqCst) {
+            return;
+        }
+        self.restart.store(true, Ordering::SeqCst);
+
+        // Holding the child lock keeps the supervisor from spawning the next
+        // decoder before this one is gone.
+        let Ok(mut child) = self.child.lock() else {
+            return;
+        };
+        // the supervisor hands the old decoder over once it sees `crossfading`
+        if !(crossfade && self.crossfade_sink()) {
+            if let Ok(s) = self.sink.lock() {
+                let paused = s.is_paused();
+                s.clear();
+                if !paused {
+                    s.play();
+                }
+            }
+            if let Some(mut c) = child.take() {
+                let _ = c.kill();
+                let _ = c.wait();
+            }
+        }
+        drop(child);
+
+        // wake the supervisor if it's waiting out a failure
+        if matches!(
+            self.connection_state(),
+            ConnectionState::Reconnecting { .. } | ConnectionState::Failed
+        ) {
+            let _ = self.signals.send(Signal::Retry);
+        }
+    }
+
+    /// Puts a fresh sink in place for the next decoder and fades it in, while
+    /// the old one fades out on a thread of its own once the new one has
+    /// audio coming out. Returns false, changing nothing, when there's
+    /// nothing to crossfade.
+    fn crossfade_sink(&self) -> bool {
+        if self.crossfade.is_zero() || self.is_paused() {
+            return false;
+        }
+        let Ok(next) = self.output.sink() else {
+            return false;
+        };
+        next.set_volume(0.0);
+        let Ok(mut sink) = self.sink.lock() else {
+            return false;
+        };
+        let old = std::mem::replace(&mut *sink, next);
+        drop(sink);
+
+        let volume = old.volume();
+        self.stats.decoded.store(false, Ordering::SeqCst);
+        self.crossfading.store(true, Ordering::SeqCst);
+
+        let stats = self.stats.clone();
+        let fade = self.crossfade;
+        thread::spawn(move || {
+            stats.wait_for_audio(CROSSFADE_WAIT_MAX);
+            let steps = (fade.as_millis() / RAMP_STEP.as_millis()).max(1) as u32;
+            for step in 1..=steps {
+                old.set_volume(volume * (1.0 - step as f32 / steps as f32));
+                thread::sleep(RAMP_STEP);
+            }
+            old.stop();
+        });
+        self.ramp(volume, fade, true, |_, _| {});
+        true
+    }
+
+    /// Resumes playback, fading in if `fade_ms` is set.
     pub fn play(&self) {
+        self.idled.store(false, Ordering::SeqCst);
+        let resuming = self.suspended.swap(false, Ordering::SeqCst);
+        // the volume from before a fade-out that's still going
+        let pausing = self.pausing.lock().ok().and_then(|mut p| p.take());
+        let volume = pausing.unwrap_or_else(|| self.volume());
         if let Ok(s) = self.sink.lock() {
+            if resuming {
+                // what was buffered before the pause is stale by now
+                s.clear();
+            }
+            if !self.fade.is_zero() && (s.is_paused() || pausing.is_some()) {
+                s.set_volume(0.0);
+            }
             s.play();
         }
+        if resuming {
+            let _ = self.signals.send(Signal::Resume);
+        }
+        if !self.fade.is_zero() {
+            self.ramp(volume, self.fade, true, |_, _| {});
+        }
     }
 
+    /// Sets the volume outright, within 0 and `max_volume`, ending any duck
+    /// or mute.
     pub fn set_volume(&self, vol: f32) {
+        // NaN would get through the clamp and silence the sink for good
+        if !vol.is_finite() {
+            return;
+        }
+        let vol = vol.clamp(0.0, self.max_volume);
+        if let Ok(mut d) = self.ducked_from.lock() {
+            *d = None;
+        }
+        if let Ok(mut m) = self.muted_from.lock() {
+            *m = None;
+        }
+        self.apply_volume(vol);
+    }
+
+    fn apply_volume(&self, vol: f32) {
+        self.volume_generation.fetch_add(1, Ordering::SeqCst);
         if let Ok(s) = self.sink.lock() {
             s.set_volume(vol);
         }
     }
 
+    /// Silences playback, remembering the volume (before any duck) to go back to.
+    pub fn mute(&self) {
+        let Ok(mut muted) = self.muted_from.lock() else {
+            return;
+        };
+        if muted.is_none() {
+            let ducked = self.ducked_from.lock().ok().and_then(|mut d| d.take());
+            *muted = Some(ducked.unwrap_or_else(|| self.volume()));
+            self.apply_volume(0.0);
+        }
+    }
+
+    pub fn unmute(&self) {
+        let restore = self.muted_from.lock().ok().and_then(|mut m| m.take());
+        if let Some(volume) = restore {
+            // muting at zero volume shouldn't leave unmute with nothing to restore
+            self.apply_volume(if volume > 0.0 { volume } else { 0.5 });
+        }
+    }
+
+    pub fn toggle_mute(&self) {
+        if self.is_muted() {
+            self.unmute();
+        } else {
+            self.mute();
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted_from.lock().map(|m| m.is_some()).unwrap_or(false)
+    }
+
+    /// The volume a mute, duck or pause will go back to, or else the
+    /// current one.
+    pub fn resting_volume(&self) -> f32 {
+        let muted = self.muted_from.lock().ok().and_then(|m| *m);
+        let ducked = self.ducked_from.lock().ok().and_then(|d| *d);
+        let pausing = self.pausing.lock().ok().and_then(|p| *p);
+        muted
+            .or(ducked)
+            .or(pausing)
+            .unwrap_or_else(|| self.volume())
+    }
+
+    /// Turns loudness normalization on or off; the gain eases over rather
+    /// than jumping.
+    pub fn toggle_normalize(&self) {
+        self.normalize.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn is_normalizing(&self) -> bool {
+        self.normalize.load(Ordering::Relaxed)
+    }
+
+    /// Moves the volume to `target` in small steps over `duration`. A later
+    /// `set_volume` or ramp cancels this one.
+    pub fn ramp_volume(&self, target: f32, duration: Duration) {
+        self.ramp(target, duration, false, |_, _| {});
+    }
+
+    /// Ramps the volume on a thread of its own, then hands the sink to
+    /// `then` along with whether the ramp got all the way (rather than being
+    /// cancelled). With `after_prebuffer`, it waits for audio to actually
+    /// start coming out first.
+    fn ramp(
+        &self,
+        target: f32,
+        duration: Duration,
+        after_prebuffer: bool,
+        then: impl FnOnce(&Sink, bool) + Send + 'static,
+    ) {
+        let generation = self.volume_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let current = self.volume_generation.clone();
+        let sink = self.sink.clone();
+        let stats = self.stats.clone();
+        let start = self.volume();
+
+        thread::spawn(move || {
+            let cancelled = || current.load(Ordering::SeqCst) != generation;
+            while after_prebuffer && stats.awaiting_audio() && !cancelled() {
+                thread::sleep(RAMP_STEP);
+            }
+
+            let steps = (duration.as_millis() / RAMP_STEP.as_millis()).max(1) as u32;
+            let mut finished = true;
+            for step in 1..=steps {
+                if cancelled() {
+                    finished = false;
+                    break;
+                }
+                let t = step as f32 / steps as f32;
+                if let Ok(s) = sink.lock() {
+                    s.set_volume(start + (target - start) * t);
+                }
+                thread::sleep(RAMP_STEP);
+            }
+            if let Ok(s) = sink.lock() {
+                then(&s, finished);
+            }
+        });
+    }
+
+    /// Temporarily lowers the volume to `level` times its current value.
+    pub fn duck(&self, level: f32, ramp: Duration) {
+        let Ok(mut ducked) = self.ducked_from.lock() else {
+            return;
+        };
+        if ducked.is_none() {
+            let volume = self.volume();
+            *ducked = Some(volume);
+            self.ramp_volume(volume * level.clamp(0.0, 1.0), ramp);
+        }
+    }
+
+    pub fn unduck(&self, ramp: Duration) {
+        let restore = self.ducked_from.lock().ok().and_then(|mut d| d.take());
+        if let Some(volume) = restore {
+            self.ramp_volume(volume, ramp);
+        }
+    }
+
+    pub fn toggle_duck(&self, level: f32, ramp: Duration) {
+        if self.is_ducked() {
+            self.unduck(ramp);
+        } else {
+            self.duck(level, ramp);
+        }
+    }
+
+    pub fn is_ducked(&self) -> bool {
+        self.ducked_from
+            .lock()
+            .map(|d| d.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Also true while fading out to pause.
     pub fn is_paused(&self) -> bool {
+        if self.pausing.lock().map(|p| p.is_some()).unwrap_or(false) {
+            return true;
+        }
         if let Ok(s) = self.sink.lock() {
             s.is_paused()
         } else {
@@ -70,181 +664,1857 @@ impl PlayerControl {
             0.0
         }
     }
-}
-
-pub async fn pick_stream(_client: &reqwest::Client) -> Option<String> {
-    STREAM_CANDIDATES.first().map(|s| s.to_string())
-}
 
-pub fn spawn_ffmpeg_to_rodio(stream_url: &str) -> Result<(PlayerControl, SinkInfo)> {
-    let (stream, stream_handle) = OutputStream::try_default().map_err(|e| {
-        anyhow!(
-            "Failed to initialize audio output: {}. Check your audio drivers.",
-            e
-        )
-    })?;
+    pub fn connection_state(&self) -> ConnectionState {
+        self.state
+            .lock()
+            .map(|s| s.clone())
+            .unwrap_or(ConnectionState::Failed)
+    }
 
-    let sink =
-        Sink::try_new(&stream_handle).map_err(|e| anyhow!("Failed to create audio sink: {}", e))?;
+    /// What went wrong the last time the stream was tried, if it hasn't
+    /// played since.
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().ok().and_then(|e| e.clone())
+    }
 
-    sink.set_volume(0.5);
+    pub fn stream_url(&self) -> String {
+        self.url.lock().map(|u| u.clone()).unwrap_or_default()
+    }
 
-    let sink_arc = Arc::new(Mutex::new(sink));
-    let stream_arc = Arc::new(stream);
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
 
-    let mut child = Command::new("ffmpeg")
-        .arg("-reconnect")
-        .arg("1")
-        .arg("-reconnect_streamed")
-        .arg("1")
-        .arg("-reconnect_delay_max")
-        .arg("5")
-        .arg("-i")
-        .arg(stream_url)
-        .arg("-f")
-        .arg("s16le")
-        .arg("-acodec")
-        .arg("pcm_s16le")
-        .arg("-ar")
-        .arg("44100")
-        .arg("-ac")
-        .arg("2")
-        .arg("-hide_banner")
-        .arg("-loglevel")
-        .arg("error")
-        .arg("-")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()
-        .map_err(|e| anyhow!("Failed to spawn ffmpeg: {}. Is ffmpeg installed?", e))?;
+    pub fn recording(&self) -> Option<String> {
+        let recorder = self.recorder.lock().ok()?;
+        recorder.as_ref().map(|r| r.path().display().to_string())
+    }
 
-    let stdout = child
-        .stdout
-        .take()
-        .ok_or_else(|| anyhow!("Failed to capture ffmpeg stdout"))?;
+    /// Starts writing what's played to a WAV file at `path`, unless a
+    /// recording is already going.
+    pub fn start_recording(&self, path: &Path) -> Result<()> {
+        let mut recorder = self
+            .recorder
+            .lock()
+            .map_err(|_| anyhow!("recorder lock poisoned"))?;
+        if recorder.is_none() {
+            *recorder = Some(Recorder::start(path, &self.bus)?);
+        }
+        Ok(())
+    }
 
-    let (tx, rx) = mpsc::sync_channel::<Vec<i16>>(10);
+    /// Finishes the current recording, if there is one, and returns where
+    /// it was saved.
+    pub fn stop_recording(&self) -> Result<Option<PathBuf>> {
+        let recorder = self.recorder.lock().ok().and_then(|mut r| r.take());
+        recorder.map(Recorder::finish).transpose()
+    }
 
-    thread::spawn(move || {
-        let mut reader = BufReader::new(stdout);
-        let mut buf = [0u8; 8192];
+    /// Whether any audio from the stream has reached the sink yet.
+    pub fn heard_audio(&self) -> bool {
+        self.stats.decoded.load(Ordering::SeqCst)
+    }
 
-        loop {
-            match reader.read(&mut buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let mut samples = Vec::with_capacity(n / 2);
-                    let mut i = 0usize;
-                    while i + 1 < n {
-                        let lo = buf[i] as u16;
-                        let hi = buf[i + 1] as u16;
-                        let sample = ((hi << 8) | lo) as i16;
-                        samples.push(sample);
-                        i += 2;
-                    }
+    pub fn underruns(&self) -> u64 {
+        self.stats.underruns.load(Ordering::Relaxed)
+    }
 
-                    if tx.send(samples).is_err() {
-                        break;
-                    }
-                }
-                Err(_) => break,
-            }
+    /// How full the pre-buffer is, from 0 to 1, while a source is waiting to start.
+    pub fn prebuffer_fill(&self) -> Option<f32> {
+        if !self.stats.prebuffering.load(Ordering::Relaxed) {
+            return None;
         }
-    });
+        self.buffer_fill()
+    }
 
-    let source = FfmpegSource::new(rx, 2, 44100);
-    let sink_for_append = sink_arc.clone();
+    /// How much audio is buffered relative to the pre-buffer size, once playing.
+    pub fn buffer_health(&self) -> Option<f32> {
+        if self.stats.prebuffering.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.buffer_fill()
+    }
 
-    thread::spawn(move || {
-        if let Ok(sink) = sink_for_append.lock() {
-            sink.append(source);
-            thread::sleep(std::time::Duration::from_millis(100));
+    fn buffer_fill(&self) -> Option<f32> {
+        let target = self.stats.buffer_target.load(Ordering::Relaxed);
+        if target == 0 {
+            return None;
         }
-    });
+        let buffered = self.stats.buffered.load(Ordering::Relaxed);
+        Some((buffered as f32 / target as f32).min(1.0))
+    }
 
-    thread::sleep(std::time::Duration::from_millis(200));
+    /// How long what's buffered will take to play.
+    pub fn buffered_duration(&self) -> Option<Duration> {
+        let rate = self.stats.buffer_rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return None;
+        }
+        let buffered = self.stats.buffered.load(Ordering::Relaxed);
+        Some(Duration::from_millis(buffered * 1000 / rate))
+    }
 
-    let control = PlayerControl {
-        child: Arc::new(Mutex::new(Some(child))),
-        sink: sink_arc,
-        _stream: stream_arc,
-    };
+    /// Share of played samples that were silence filling in for late audio.
+    pub fn starved_ratio(&self) -> f32 {
+        let played = self.stats.played_samples.load(Ordering::Relaxed);
+        let silent = self.stats.silent_samples.load(Ordering::Relaxed);
+        if played == 0 {
+            0.0
+        } else {
+            silent as f32 / played as f32
+        }
+    }
 
-    Ok((
-        control,
-        SinkInfo {
-            _channels: 2,
-            _sample_rate: 44100,
-        },
-    ))
+    /// Starts reconnecting again after the player gave up on the stream.
+    pub fn retry(&self) {
+        let _ = self.signals.send(Signal::Retry);
+    }
 }
 
-struct FfmpegSource {
-    rx: mpsc::Receiver<Vec<i16>>,
-    buffer: VecDeque<i16>,
-    channels: u16,
-    sample_rate: u32,
-}
+/// The candidates that answer with audio, in order (`preferred_format` first,
+/// if given). If none do, all of them, to keep trying.
+pub async fn pick_streams(client: &reqwest::Client, preferred_format: Option<&str>) -> Vec<String> {
+    let mut candidates = STREAM_CANDIDATES.to_vec();
+    if let Some(format) = preferred_format {
+        candidates.sort_by_key(|url| !url.ends_with(&format!("/{}", format)));
+    }
 
-impl FfmpegSource {
-    fn new(rx: mpsc::Receiver<Vec<i16>>, channels: u16, sample_rate: u32) -> Self {
-        FfmpegSource {
-            rx,
-            buffer: VecDeque::with_capacity(8192),
-            channels,
-            sample_rate,
+    let mut answering = Vec::new();
+    for url in &candidates {
+        match probe_stream(client, url).await {
+            Ok(content_type) => {
+                info!(url, content_type, "stream answered");
+                answering.push(url.to_string());
+            }
+            Err(e) => info!(url, error = %e, "skipping stream"),
         }
     }
+    if answering.is_empty() {
+        warn!("no stream answered; trying them all anyway");
+        return candidates.into_iter().map(str::to_string).collect();
+    }
+    answering
 }
 
-impl Iterator for FfmpegSource {
-    type Item = f32;
+/// GETs just the headers of `url` and returns its content type if it's audio.
+async fn probe_stream(client: &reqwest::Client, url: &str) -> Result<String> {
+    let resp = client
+        .get(url)
+        .timeout(Duration::from_millis(2500))
+        .send()
+        .await?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if let Some(s) = self.buffer.pop_front() {
-                return Some(s as f32 / 32768.0);
-            }
+    if !resp.status().is_success() {
+        return Err(anyhow!("status {}", resp.status()));
+    }
 
-            match self.rx.try_recv() {
-                Ok(chunk) => {
-                    for v in chunk {
-                        self.buffer.push_back(v);
-                    }
-                    continue;
-                }
-                Err(mpsc::TryRecvError::Empty) => {
-                    if self.buffer.is_empty() {
-                        match self.rx.recv_timeout(std::time::Duration::from_millis(100)) {
-                            Ok(chunk) => {
-                                for v in chunk {
-                                    self.buffer.push_back(v);
-                                }
-                                continue;
-                            }
-                            Err(mpsc::RecvTimeoutError::Timeout) => continue,
-                            Err(mpsc::RecvTimeoutError::Disconnected) => return None,
-                        }
-                    }
-                    return Some(0.0);
-                }
-                Err(mpsc::TryRecvError::Disconnected) => return None,
-            }
-        }
+    let content_type = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    // Dropping the response closes the connection before the audio body is read.
+    if content_type.starts_with("audio/") || content_type == "application/ogg" {
+        Ok(content_type)
+    } else {
+        Err(anyhow!("unexpected content type {:?}", content_type))
     }
 }
 
-impl Source for FfmpegSource {
-    fn current_frame_len(&self) -> Option<usize> {
-        None
-    }
-    fn channels(&self) -> u16 {
-        self.channels
+/// Names of the audio outputs, for `output_device`.
+pub fn output_devices() -> Result<Vec<String>> {
+    let devices = rodio::cpal::default_host().output_devices()?;
+    Ok(devices.filter_map(|d| d.name().ok()).collect())
+}
+
+/// The first output whose name contains `device`, ignoring case.
+fn find_output(device: &str) -> Result<Option<rodio::cpal::Device>> {
+    let wanted = device.to_lowercase();
+    Ok(rodio::cpal::default_host()
+        .output_devices()?
+        .find(|d| d.name().is_ok_and(|n| n.to_lowercase().contains(&wanted))))
+}
+
+/// Whether `output_device` names an output there is, so it can be said
+/// before starting that the default will be used instead.
+pub fn has_output(device: &str) -> bool {
+    find_output(device).is_ok_and(|found| found.is_some())
+}
+
+/// The output `find_output` finds for `device`, or the default one if
+/// there's no such output (or no name was given).
+fn open_output(device: Option<&str>) -> Result<(OutputStream, OutputStreamHandle)> {
+    if let Some(wanted) = device {
+        match find_output(wanted)? {
+            Some(device) => return Ok(OutputStream::try_from_device(&device)?),
+            None => warn!(device = wanted, "no such audio output; using the default"),
+        }
     }
-    fn sample_rate(&self) -> u32 {
+    Ok(OutputStream::try_default()?)
+}
+
+/// Starts playing the first of `stream_urls` that the decoder gets audio out
+/// of, and returns which one that was once its audio is flowing. Titles the
+/// stream sends along with the audio are published to `titles`. Failing to
+/// start, the error is a `StartError` saying whether it was the decoder, the
+/// audio output, or that no audio came.
+pub fn spawn_ffmpeg_to_rodio(
+    stream_urls: &[String],
+    config: &Config,
+    titles: watch::Sender<Option<String>>,
+) -> Result<(PlayerControl, SinkInfo, String)> {
+    let Some(stream_url) = stream_urls.first() else {
+        bail!("No stream to play");
+    };
+
+    // Spawn the first ffmpeg here so a missing binary is reported at startup,
+    // or with `auto`, so symphonia can take over.
+    let (backend, decoder): (Backend, Box<dyn Decode>) = match config.backend {
+        Backend::Ffmpeg => {
+            probe_ffmpeg()?;
+            (Backend::Ffmpeg, Box::new(Ffmpeg::new(spawn_ffmpeg()?)))
+        }
+        Backend::Symphonia => (Backend::Symphonia, Box::new(Symphonia)),
+        Backend::Auto => match probe_ffmpeg().and_then(|_| spawn_ffmpeg()) {
+            Ok(child) => (Backend::Ffmpeg, Box::new(Ffmpeg::new(child))),
+            Err(e) => {
+                warn!(error = %e, "decoding with symphonia instead of ffmpeg");
+                (Backend::Symphonia, Box::new(Symphonia))
+            }
+        },
+    };
+
+    let (stream, handle) = open_output(config.output_device.as_deref())
+        .map_err(|e| StartError::AudioOutput(e.to_string()))?;
+    let output = Device {
+        handle,
+        _stream: stream,
+    };
+    let (control, sink_info) = start(
+        stream_url,
+        config,
+        backend,
+        Box::new(output),
+        decoder,
+        titles,
+    )?;
+
+    let playing = control.first_that_plays(stream_urls)?;
+    Ok((control, sink_info, playing))
+}
+
+/// Where sinks play.
+trait Output {
+    fn sink(&self) -> Result<Sink, PlayError>;
+}
+
+/// An audio device, opened with `open_output`.
+struct Device {
+    handle: OutputStreamHandle,
+    _stream: OutputStream, // must keep alive or audio stops
+}
+
+impl Output for Device {
+    fn sink(&self) -> Result<Sink, PlayError> {
+        Sink::try_new(&self.handle)
+    }
+}
+
+/// What the supervisor runs to get audio into the sink, once per connection.
+trait Decode: Send {
+    /// Decodes `pipeline`'s stream into the sources it `attach`es, until the
+    /// stream ends or `keep_going` says to stop. Returns whether any audio
+    /// came out.
+    fn run(&mut self, pipeline: &Pipeline) -> bool;
+}
+
+/// ffmpeg, fed by `spawn_download`. The first process is started up front so
+/// a missing binary shows at startup.
+struct Ffmpeg {
+    first: Option<Child>,
+}
+
+impl Ffmpeg {
+    fn new(first: Child) -> Self {
+        Self { first: Some(first) }
+    }
+}
+
+impl Decode for Ffmpeg {
+    fn run(&mut self, pipeline: &Pipeline) -> bool {
+        pipeline.run_ffmpeg(self.first.take())
+    }
+}
+
+struct Symphonia;
+
+impl Decode for Symphonia {
+    fn run(&mut self, pipeline: &Pipeline) -> bool {
+        pipeline.run_symphonia()
+    }
+}
+
+/// Builds the player on `output` and has the supervisor start running
+/// `decoder` on `stream_url`.
+fn start(
+    stream_url: &str,
+    config: &Config,
+    backend: Backend,
+    output: Box<dyn Output>,
+    decoder: Box<dyn Decode>,
+    titles: watch::Sender<Option<String>>,
+) -> Result<(PlayerControl, SinkInfo)> {
+    let sink = output
+        .sink()
+        .map_err(|e| StartError::AudioOutput(format!("couldn't create a sink: {}", e)))?;
+    let max_volume = config.max_volume.clamp(0.0, 2.0);
+    let volume = config.default_volume.clamp(0.0, max_volume);
+    let fade = Duration::from_millis(config.fade_ms);
+    sink.set_volume(if fade.is_zero() && !config.start_muted {
+        volume
+    } else {
+        0.0
+    });
+
+    let eq_gains = eq::preset(&config.eq.preset, &config.eq.presets).unwrap_or_else(|| {
+        warn!(preset = %config.eq.preset, "no such EQ preset; starting flat");
+        [0.0; 5]
+    });
+
+    let pipeline = Pipeline {
+        url: Arc::new(Mutex::new(stream_url.to_string())),
+        stats: Arc::new(PlaybackStats {
+            idle_after_ms: AtomicU64::new(u64::from(config.idle_timeout_secs) * 1000),
+            ..Default::default()
+        }),
+        backend,
+        max_attempts: config.max_reconnect_attempts,
+        delay_max_secs: config.reconnect_delay_max_secs,
+        stall_timeout_secs: config.stall_timeout_secs,
+        http: config.http()?,
+        sink: Arc::new(Mutex::new(sink)),
+        child: Arc::new(Mutex::new(None)),
+        state: Arc::new(Mutex::new(ConnectionState::Connecting)),
+        last_error: Arc::default(),
+        stopping: Arc::new(AtomicBool::new(false)),
+        suspended: Arc::new(AtomicBool::new(false)),
+        restart: Arc::new(AtomicBool::new(false)),
+        crossfade: Duration::from_millis(config.crossfade_ms),
+        crossfading: Arc::new(AtomicBool::new(false)),
+        fading: Arc::new(Mutex::new(Vec::new())),
+        bus: Arc::new(SampleBus::default()),
+        buffering: Buffering {
+            prebuffer: Duration::from_millis(config.prebuffer_ms),
+            max: Duration::from_millis(config.max_buffer_ms),
+        },
+        recent: Arc::new(Mutex::new(VecDeque::with_capacity(RECENT_SAMPLES))),
+        levels: Arc::new(Levels::default()),
+        stream_info: Arc::new(Mutex::new(None)),
+        normalize: Arc::new(AtomicBool::new(config.normalize)),
+        normalize_target_db: config.normalize_target_db,
+        mono: Arc::new(AtomicBool::new(config.mono)),
+        eq: Arc::new(EqGains::new(eq_gains)),
+        titles,
+    };
+    let recent = pipeline.recent.clone();
+    let levels = pipeline.levels.clone();
+    let stream_info = pipeline.stream_info.clone();
+
+    let recorder = config
+        .record_to
+        .as_deref()
+        .map(|path| Recorder::start(path, &pipeline.bus))
+        .transpose()?;
+
+    let (signals_tx, signals_rx) = mpsc::channel();
+
+    let control = PlayerControl {
+        child: pipeline.child.clone(),
+        sink: pipeline.sink.clone(),
+        url: pipeline.url.clone(),
+        backend: pipeline.backend,
+        stats: pipeline.stats.clone(),
+        state: pipeline.state.clone(),
+        last_error: pipeline.last_error.clone(),
+        volume_generation: Arc::new(AtomicU64::new(0)),
+        ducked_from: Mutex::new(None),
+        muted_from: Mutex::new(config.start_muted.then_some(volume)),
+        stopping: pipeline.stopping.clone(),
+        suspended: pipeline.suspended.clone(),
+        idled: AtomicBool::new(false),
+        restart: pipeline.restart.clone(),
+        pause_disconnects: config.pause_disconnects,
+        max_volume,
+        fade,
+        pausing: Arc::new(Mutex::new(None)),
+        crossfade: pipeline.crossfade,
+        crossfading: pipeline.crossfading.clone(),
+        fading: pipeline.fading.clone(),
+        normalize: pipeline.normalize.clone(),
+        mono: pipeline.mono.clone(),
+        eq: pipeline.eq.clone(),
+        signals: signals_tx,
+        bus: pipeline.bus.clone(),
+        recorder: Mutex::new(recorder),
+        output,
+    };
+
+    thread::spawn(move || pipeline.supervise(decoder, signals_rx));
+    if !fade.is_zero() && !config.start_muted {
+        control.ramp(volume, fade, true, |_, _| {});
+    }
+
+    Ok((
+        control,
+        SinkInfo {
+            _channels: 2,
+            _sample_rate: 44100,
+            recent,
+            levels,
+            stream_info,
+        },
+    ))
+}
+
+struct Pipeline {
+    url: Arc<Mutex<String>>,
+    stats: Arc<PlaybackStats>,
+    backend: Backend,
+    max_attempts: u32,
+    delay_max_secs: u32,
+    stall_timeout_secs: u32,
+    http: Http,
+    sink: Arc<Mutex<Sink>>,
+    child: Arc<Mutex<Option<Child>>>,
+    state: Arc<Mutex<ConnectionState>>,
+    stopping: Arc<AtomicBool>,
+    suspended: Arc<AtomicBool>,
+    restart: Arc<AtomicBool>,
+    crossfade: Duration,
+    crossfading: Arc<AtomicBool>,
+    fading: Arc<Mutex<Vec<Child>>>,
+    bus: Arc<SampleBus>,
+    buffering: Buffering,
+    recent: RecentSamples,
+    levels: Arc<Levels>,
+    stream_info: Arc<Mutex<Option<StreamInfo>>>,
+    normalize: Arc<AtomicBool>,
+    normalize_target_db: f32,
+    mono: Arc<AtomicBool>,
+    eq: Arc<EqGains>,
+    titles: watch::Sender<Option<String>>,
+    last_error: LastError,
+}
+
+/// Why the player couldn't start, so the right advice can go with it.
+#[derive(Debug)]
+pub enum StartError {
+    /// There's no `ffmpeg` on the PATH.
+    FfmpegMissing,
+    /// `ffmpeg` is there but wouldn't run.
+    Decoder(String),
+    /// No audio output could be opened.
+    AudioOutput(String),
+    /// None of the streams got any audio through in time. Holds the last
+    /// thing that went wrong, if anything did.
+    NoAudio(Option<String>),
+}
+
+impl StartError {
+    /// What to try next, for this platform.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            StartError::FfmpegMissing if cfg!(target_os = "macos") => {
+                "Install it with `brew install ffmpeg`, or set backend = \"symphonia\" to decode without it."
+            }
+            StartError::FfmpegMissing if cfg!(windows) => {
+                "Install it with `winget install ffmpeg` and open a new terminal, or set backend = \"symphonia\" to decode without it."
+            }
+            StartError::FfmpegMissing => {
+                "Install it with your package manager (e.g. `sudo apt install ffmpeg` or `sudo pacman -S ffmpeg`), or set backend = \"symphonia\" to decode without it."
+            }
+            StartError::Decoder(_) => {
+                "Check that `ffmpeg -version` works, or set backend = \"symphonia\" to decode without it."
+            }
+            StartError::AudioOutput(_) if cfg!(target_os = "linux") => {
+                "Make sure ALSA can reach a sound card, or that PulseAudio or PipeWire is running; --list-devices shows what's available."
+            }
+            StartError::AudioOutput(_) => {
+                "Make sure your audio drivers are installed and an output device is connected; --list-devices shows what's available."
+            }
+            StartError::NoAudio(_) => {
+                "Check your internet connection, or try another stream_url or preferred_format in the config."
+            }
+        }
+    }
+
+    fn spawning_ffmpeg(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::NotFound {
+            StartError::FfmpegMissing
+        } else {
+            StartError::Decoder(format!("couldn't run ffmpeg: {}", e))
+        }
+    }
+}
+
+impl std::fmt::Display for StartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StartError::FfmpegMissing => {
+                write!(f, "ffmpeg isn't installed (or isn't on your PATH)")
+            }
+            StartError::Decoder(e) => write!(f, "Failed to start the decoder: {}", e),
+            StartError::AudioOutput(e) => write!(f, "Failed to initialize audio output: {}", e),
+            StartError::NoAudio(Some(e)) => write!(f, "No audio arrived from the stream: {}", e),
+            StartError::NoAudio(None) => write!(
+                f,
+                "No audio arrived from the stream within {} seconds",
+                FIRST_AUDIO_TIMEOUT.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StartError {}
+
+/// Checks that ffmpeg is there and runs, before anything's been set up.
+fn probe_ffmpeg() -> Result<(), StartError> {
+    let status = Command::new("ffmpeg")
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(StartError::spawning_ffmpeg)?;
+    if !status.success() {
+        return Err(StartError::Decoder(format!("`ffmpeg -version` {}", status)));
+    }
+    Ok(())
+}
+
+/// ffmpeg decodes from stdin; the download is ours so the ICY metadata can
+/// be taken out of it (see `Pipeline::spawn_download`).
+fn spawn_ffmpeg() -> Result<Child, StartError> {
+    Command::new("ffmpeg")
+        .arg("-i")
+        .arg("pipe:0")
+        .arg("-f")
+        .arg("s16le")
+        .arg("-acodec")
+        .arg("pcm_s16le")
+        .arg("-ar")
+        .arg("44100")
+        .arg("-ac")
+        .arg("2")
+        .arg("-hide_banner")
+        .arg("-nostats")
+        // info level, for the input's format (see `Pipeline::watch_stderr`)
+        .arg("-loglevel")
+        .arg("info")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(StartError::spawning_ffmpeg)
+}
+
+impl Pipeline {
+    fn url(&self) -> String {
+        self.url.lock().map(|u| u.clone()).unwrap_or_default()
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        if let Ok(mut s) = self.state.lock() {
+            *s = state;
+        }
+    }
+
+    /// False once the decoder should wind down: stopping, pausing with
+    /// `pause_disconnects`, or starting over.
+    fn keep_going(&self) -> bool {
+        !self.stopping.load(Ordering::SeqCst)
+            && !self.suspended.load(Ordering::SeqCst)
+            && !self.restart.load(Ordering::SeqCst)
+    }
+
+    /// Runs the decoder until the stream ends, restarting it with a growing
+    /// delay until the attempt cap is hit.
+    fn supervise(self, mut decoder: Box<dyn Decode>, signals: mpsc::Receiver<Signal>) {
+        let mut attempts = 0u32;
+
+        loop {
+            self.restart.store(false, Ordering::SeqCst);
+            self.crossfading.store(false, Ordering::SeqCst);
+            let got_audio = decoder.run(&self);
+
+            if self.stopping.load(Ordering::SeqCst) {
+                return;
+            }
+
+            if self.restart.load(Ordering::SeqCst) {
+                info!(url = self.url(), "decoder restarting");
+                attempts = 0;
+                continue;
+            }
+
+            if self.suspended.load(Ordering::SeqCst) {
+                // Paused and disconnected; pick the stream up again once resumed.
+                loop {
+                    match signals.recv() {
+                        Ok(Signal::Resume) => break,
+                        Ok(Signal::Retry) => continue,
+                        Ok(Signal::Stop) | Err(_) => return,
+                    }
+                }
+                attempts = 0;
+                continue;
+            }
+
+            if got_audio {
+                attempts = 0;
+            } else {
+                self.stats.run_failed();
+            }
+            attempts += 1;
+
+            if attempts > self.max_attempts {
+                warn!(attempts = attempts - 1, "giving up on the stream");
+                self.set_state(ConnectionState::Failed);
+                loop {
+                    match signals.recv() {
+                        Ok(Signal::Retry) => break,
+                        Ok(Signal::Resume) => continue,
+                        Ok(Signal::Stop) | Err(_) => return,
+                    }
+                }
+                attempts = 0;
+            } else {
+                self.set_state(ConnectionState::Reconnecting { attempt: attempts });
+                let backoff = BACKOFF_FIRST
+                    .saturating_mul(1 << (attempts - 1).min(16))
+                    .min(BACKOFF_MAX);
+                warn!(
+                    attempt = attempts,
+                    got_audio,
+                    backoff_ms = backoff.as_millis() as u64,
+                    "decoder stopped; reconnecting"
+                );
+                match signals.recv_timeout(backoff) {
+                    Ok(Signal::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    Ok(Signal::Retry | Signal::Resume) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                }
+            }
+        }
+    }
+
+    fn run_ffmpeg(&self, spawned: Option<Child>) -> bool {
+        let spawned = match spawned {
+            Some(child) => Ok(child),
+            None => spawn_ffmpeg(),
+        };
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                record(&self.last_error, e);
+                return false;
+            }
+        };
+
+        let stdout = child.stdout.take();
+        if let Some(stdin) = child.stdin.take() {
+            self.spawn_download(stdin);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            self.watch_stderr(stderr);
+        }
+        if let Ok(mut guard) = self.child.lock() {
+            *guard = Some(child);
+        }
+        if !self.keep_going() {
+            self.reap();
+            return false;
+        }
+
+        let Some(stdout) = stdout else {
+            self.reap();
+            return false;
+        };
+        let mut samples = SampleReader::new(BufReader::new(stdout));
+        let feed = self.pump(&mut samples);
+        let got_audio = feed.is_some();
+        let crossfading = self.crossfading.swap(false, Ordering::SeqCst);
+        match feed {
+            Some(feed) if crossfading => self.hand_off(samples, feed),
+            feed => {
+                // before reaping, which can take a moment, so the source
+                // doesn't pad the wait with silence
+                if let Some(feed) = feed {
+                    feed.end();
+                }
+                self.reap();
+            }
+        }
+        got_audio
+    }
+
+    /// Picks the input's format out of what ffmpeg logs. All of it goes to
+    /// the debug log, and is read so ffmpeg never blocks writing it.
+    fn watch_stderr(&self, stderr: ChildStderr) {
+        let info = self.stream_info.clone();
+        if let Ok(mut info) = info.lock() {
+            *info = None;
+        }
+
+        thread::spawn(move || {
+            let mut found = false;
+            for line in BufReader::new(stderr).split(b'\n') {
+                let Ok(line) = line else { break };
+                let line = String::from_utf8_lossy(&line);
+                debug!("ffmpeg: {}", line.trim_end());
+                if found {
+                    continue;
+                }
+                // the input is logged before the output, which is always PCM
+                if let Some(parsed) = parse_stream_line(&line) {
+                    found = true;
+                    if let Ok(mut info) = info.lock() {
+                        *info = Some(parsed);
+                    }
+                }
+            }
+        });
+    }
+
+    fn run_symphonia(&self) -> bool {
+        let mut got_audio = false;
+        let decoded = decoder::decode_stream(
+            &self.url(),
+            &self.http,
+            self.titles.clone(),
+            || self.keep_going(),
+            |channels, sample_rate| {
+                got_audio = true;
+                self.attach(channels, sample_rate)
+            },
+        );
+        if let Err(e) = decoded {
+            record(&self.last_error, format!("{:#}", e));
+        }
+        got_audio
+    }
+
+    /// Downloads the stream into ffmpeg's stdin. Like ffmpeg's own
+    /// `-reconnect_streamed`, a connection that drops after audio was flowing
+    /// is retried with doubling delays up to `delay_max_secs`; after that stdin
+    /// is closed, ffmpeg finishes and `supervise` takes over.
+    fn spawn_download(&self, mut stdin: ChildStdin) {
+        let url = self.url();
+        let titles = self.titles.clone();
+        let http = self.http.clone();
+        let stopping = self.stopping.clone();
+        let last_error = self.last_error.clone();
+        let read_timeout = (self.stall_timeout_secs > 0)
+            .then(|| Duration::from_secs(self.stall_timeout_secs.into()));
+        let delay_max = Duration::from_secs(self.delay_max_secs.into());
+
+        thread::spawn(move || {
+            // None until audio has been flowing
+            let mut delay = None;
+            loop {
+                match download(&url, read_timeout, &http, &titles, &last_error, &mut stdin) {
+                    Ok(true) => delay = Some(Duration::ZERO),
+                    Ok(false) => {}
+                    // ffmpeg has gone away
+                    Err(_) => return,
+                }
+
+                let Some(wait) = delay else { return };
+                if wait > delay_max || stopping.load(Ordering::SeqCst) {
+                    return;
+                }
+                thread::sleep(wait);
+                delay = Some(if wait.is_zero() {
+                    Duration::from_secs(1)
+                } else {
+                    wait * 2
+                });
+            }
+        });
+    }
+
+    /// Queues a fresh source on the sink and returns the feed for it and the bus.
+    fn attach(&self, channels: u16, sample_rate: u32) -> Feed {
+        let (tx, rx) = mpsc::sync_channel::<Chunk>(10);
+        let ended = Arc::new(AtomicBool::new(false));
+        let mono = self.mono.load(Ordering::SeqCst);
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = None;
+        }
+        if let Ok(sink) = self.sink.lock() {
+            let source = FfmpegSource::new(
+                Incoming {
+                    chunks: rx,
+                    ended: ended.clone(),
+                },
+                if mono { 1 } else { channels },
+                sample_rate,
+                self.stats.clone(),
+                self.buffering,
+                self.recent.clone(),
+                self.levels.clone(),
+            );
+            sink.append(Normalized::new(
+                Equalized::new(source, self.eq.clone()),
+                self.normalize.clone(),
+                self.normalize_target_db,
+            ));
+        }
+        self.set_state(ConnectionState::Connected);
+        Feed::new(tx, ended, self.bus.clone(), channels, sample_rate, mono)
+    }
+
+    /// Feeds one ffmpeg process into the sink until it ends or a crossfade
+    /// starts. Returns the feed, if any audio arrived.
+    fn pump(&self, samples: &mut SampleReader<impl Read>) -> Option<Feed> {
+        let mut feed = None;
+        samples.run(|batch| {
+            feed.get_or_insert_with(|| self.attach(2, 44100))
+                .send(batch)
+                && !self.crossfading.load(Ordering::SeqCst)
+        });
+        feed
+    }
+
+    /// Lets the decoder being switched away from play on into the old sink
+    /// while that fades out, then shuts it down.
+    fn hand_off(&self, mut samples: SampleReader<impl Read + Send + 'static>, feed: Feed) {
+        let Some(child) = self.child.lock().ok().and_then(|mut c| c.take()) else {
+            return;
+        };
+        let id = child.id();
+        if let Ok(mut fading) = self.fading.lock() {
+            fading.push(child);
+        }
+        let feed = feed.live_only();
+        thread::spawn(move || {
+            samples.run(|batch| feed.send(batch));
+            feed.end();
+        });
+
+        let stats = self.stats.clone();
+        let fading = self.fading.clone();
+        let fade = self.crossfade;
+        thread::spawn(move || {
+            stats.wait_for_audio(CROSSFADE_WAIT_MAX);
+            thread::sleep(fade);
+            let Ok(mut fading) = fading.lock() else {
+                return;
+            };
+            if let Some(i) = fading.iter().position(|c| c.id() == id) {
+                let mut c = fading.swap_remove(i);
+                let _ = c.kill();
+                let _ = c.wait();
+            }
+        });
+    }
+
+    fn reap(&self) {
+        if let Ok(mut guard) = self.child.lock() {
+            if let Some(mut c) = guard.take() {
+                let _ = c.kill();
+                let _ = c.wait();
+            }
+        }
+    }
+}
+
+/// Reads s16le samples out of ffmpeg. However the reads split the bytes,
+/// every sample arrives whole, even across separate `run`s.
+struct SampleReader<R> {
+    reader: R,
+    carry: Option<u8>,
+}
+
+impl<R: Read> SampleReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            carry: None,
+        }
+    }
+
+    /// Reads until the reader ends or fails, or `deliver` returns false.
+    fn run(&mut self, mut deliver: impl FnMut(&[i16]) -> bool) {
+        let mut buf = [0u8; 8192];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => return,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    debug!(error = %e, "reading ffmpeg's output failed");
+                    return;
+                }
+                Ok(n) => {
+                    let samples = decode_s16le(&mut self.carry, &buf[..n]);
+                    if !samples.is_empty() && !deliver(&samples) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Reads a line like `Stream #0:0: Audio: mp3 (mp3float), 44100 Hz, stereo, fltp, 320 kb/s`.
+fn parse_stream_line(line: &str) -> Option<StreamInfo> {
+    let (_, rest) = line
+        .trim()
+        .strip_prefix("Stream #")?
+        .split_once("Audio: ")?;
+    let mut fields = rest.split(", ");
+    let codec = fields.next()?.split(' ').next()?.to_string();
+
+    let mut info = StreamInfo {
+        codec,
+        ..Default::default()
+    };
+    for field in fields {
+        // e.g. "128 kb/s (default)"
+        let field = field.split(" (").next().unwrap_or(field).trim();
+        if let Some(hz) = field.strip_suffix(" Hz") {
+            info.sample_rate = hz.parse().ok();
+        } else if let Some(kbps) = field.strip_suffix(" kb/s") {
+            info.bitrate_kbps = kbps.parse().ok();
+        } else if info.sample_rate.is_some() && info.channels.is_none() {
+            // the layout comes straight after the rate
+            info.channels = Some(field.to_string());
+        }
+    }
+    Some(info)
+}
+
+/// Turns ffmpeg's s16le output into samples. A read can end halfway through
+/// a sample; its first byte is kept in `carry` for the next call.
+fn decode_s16le(carry: &mut Option<u8>, mut bytes: &[u8]) -> Vec<i16> {
+    let mut samples = Vec::with_capacity(bytes.len().div_ceil(2));
+    if let Some(lo) = carry.take() {
+        let Some((&hi, rest)) = bytes.split_first() else {
+            *carry = Some(lo);
+            return samples;
+        };
+        samples.push(i16::from_le_bytes([lo, hi]));
+        bytes = rest;
+    }
+
+    let pairs = bytes.chunks_exact(2);
+    *carry = pairs.remainder().first().copied();
+    samples.extend(pairs.map(|pair| i16::from_le_bytes([pair[0], pair[1]])));
+    samples
+}
+
+/// Copies one connection's worth of audio into `out`, without the metadata.
+/// Returns whether any audio arrived, or an error once `out` stops taking it.
+/// Failing to connect is recorded in `last_error`.
+fn download(
+    url: &str,
+    read_timeout: Option<Duration>,
+    http: &Http,
+    titles: &watch::Sender<Option<String>>,
+    last_error: &LastError,
+    out: &mut impl Write,
+) -> io::Result<bool> {
+    let resp = match icy::connect(url, read_timeout, http) {
+        Ok(resp) => resp,
+        Err(e) => {
+            debug!(url, "couldn't connect: {:#}", e);
+            record(last_error, format!("{:#}", e));
+            return Ok(false);
+        }
+    };
+    let metaint = icy::metaint(&resp);
+    let mut audio = IcyReader::new(resp, metaint, titles.clone());
+
+    let mut buf = [0u8; 8192];
+    let mut got_audio = false;
+    loop {
+        match audio.read(&mut buf) {
+            Ok(0) | Err(_) => return Ok(got_audio),
+            Ok(n) => {
+                out.write_all(&buf[..n])?;
+                got_audio = true;
+            }
+        }
+    }
+}
+
+/// Counters shared by every source the pipeline queues, so they survive reconnects.
+#[derive(Default)]
+pub struct PlaybackStats {
+    /// Times playback ran dry and had to wait for the decoder.
+    pub underruns: AtomicU64,
+    /// Samples waiting to be played, and how many the pre-buffer aims for.
+    pub buffered: AtomicU64,
+    pub buffer_target: AtomicU64,
+    /// Samples a second, over all channels, of the source being played.
+    pub buffer_rate: AtomicU64,
+    /// Set while a new source collects its pre-buffer before playing.
+    pub prebuffering: AtomicBool,
+    /// Samples played, and how many of those were silence standing in for audio
+    /// that hadn't arrived yet.
+    pub played_samples: AtomicU64,
+    pub silent_samples: AtomicU64,
+    /// Set once audio from the decoder has reached a source.
+    pub decoded: AtomicBool,
+    /// Decoder runs that ended without producing any audio.
+    pub failed_runs: AtomicU64,
+    /// Notified when either of those changes, for `wait_for_first_audio`.
+    progress: Condvar,
+    progress_lock: Mutex<()>,
+    /// Set by `flush_to_live` until the playing source has caught up.
+    pub flush: AtomicBool,
+    /// Set by the playing source once it has played nothing but silence for
+    /// `idle_after_ms` (zero for never), and cleared by the next sound.
+    pub idle: AtomicBool,
+    pub idle_after_ms: AtomicU64,
+}
+
+impl PlaybackStats {
+    /// Whether the current decoder has yet to get any audio playing.
+    fn awaiting_audio(&self) -> bool {
+        !self.decoded.load(Ordering::SeqCst) || self.prebuffering.load(Ordering::Relaxed)
+    }
+
+    /// Called by a source when its first chunk arrives.
+    fn audio_arrived(&self) {
+        self.decoded.store(true, Ordering::SeqCst);
+        self.notify_progress();
+    }
+
+    /// Called by the supervisor when a decoder run got no audio out.
+    fn run_failed(&self) {
+        self.failed_runs.fetch_add(1, Ordering::SeqCst);
+        self.notify_progress();
+    }
+
+    fn notify_progress(&self) {
+        // taken so a waiter can't miss this between checking and sleeping
+        let _guard = self.progress_lock.lock();
+        self.progress.notify_all();
+    }
+
+    /// Waits until audio reaches a source, a decoder run fails after the
+    /// `failed_before` that had, or `limit` passes. Returns whether audio came.
+    fn wait_for_first_audio(&self, failed_before: u64, limit: Duration) -> bool {
+        if let Ok(guard) = self.progress_lock.lock() {
+            let _ = self.progress.wait_timeout_while(guard, limit, |_| {
+                !self.decoded.load(Ordering::SeqCst)
+                    && self.failed_runs.load(Ordering::SeqCst) == failed_before
+            });
+        }
+        self.decoded.load(Ordering::SeqCst)
+    }
+
+    /// Waits until audio is playing, or `limit` has passed.
+    fn wait_for_audio(&self, limit: Duration) {
+        let deadline = std::time::Instant::now() + limit;
+        while self.awaiting_audio() && std::time::Instant::now() < deadline {
+            thread::sleep(RAMP_STEP);
+        }
+    }
+}
+
+/// How much audio a source collects before playing, and the most it holds.
+#[derive(Clone, Copy, Debug, Default)]
+struct Buffering {
+    prebuffer: Duration,
+    /// Zero for no limit.
+    max: Duration,
+}
+
+struct FfmpegSource {
+    incoming: Incoming,
+    buffer: VecDeque<i16>,
+    channels: u16,
+    sample_rate: u32,
+    stats: Arc<PlaybackStats>,
+    // true until the first chunk arrives, so the initial fill isn't an underrun
+    starved: bool,
+    // set with the first chunk, which is when the stats hear audio is flowing
+    heard: bool,
+    // samples to collect before playing anything
+    target: usize,
+    prebuffering: bool,
+    // past this many samples the oldest are dropped; never below `target`
+    cap: Option<usize>,
+    recent: RecentSamples,
+    tap: Vec<f32>,
+    frame_sum: f32,
+    frame_pos: u16,
+    levels: Arc<Levels>,
+    // sums of squares for the left and right channels over the current VU block
+    vu_sums: [f32; 2],
+    // and their loudest samples
+    vu_peaks: [f32; 2],
+    vu_frames: u32,
+    // samples of silence in a row, and how many make the stream idle
+    silent_run: u64,
+    idle_after: Option<u64>,
+}
+
+impl FfmpegSource {
+    fn new(
+        incoming: Incoming,
+        channels: u16,
+        sample_rate: u32,
+        stats: Arc<PlaybackStats>,
+        buffering: Buffering,
+        recent: RecentSamples,
+        levels: Arc<Levels>,
+    ) -> Self {
+        // a flush asked for before this source existed was for the last one
+        stats.flush.store(false, Ordering::Relaxed);
+        let rate = channels as u64 * sample_rate as u64;
+        let samples = |time: Duration| (rate as u128 * time.as_millis() / 1000) as usize;
+        let target = samples(buffering.prebuffer);
+        let cap = (!buffering.max.is_zero()).then(|| samples(buffering.max).max(target));
+        stats.buffered.store(0, Ordering::Relaxed);
+        stats.buffer_rate.store(rate, Ordering::Relaxed);
+        stats.buffer_target.store(target as u64, Ordering::Relaxed);
+        stats.prebuffering.store(target > 0, Ordering::Relaxed);
+        stats.idle.store(false, Ordering::Relaxed);
+        let idle_after = match stats.idle_after_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(rate * ms / 1000),
+        };
+
+        FfmpegSource {
+            incoming,
+            buffer: VecDeque::with_capacity(target.max(8192)),
+            channels,
+            sample_rate,
+            stats,
+            starved: true,
+            heard: false,
+            target,
+            prebuffering: target > 0,
+            cap,
+            recent,
+            tap: Vec::with_capacity(TAP_BATCH),
+            frame_sum: 0.0,
+            frame_pos: 0,
+            levels,
+            vu_sums: [0.0; 2],
+            vu_peaks: [0.0; 2],
+            vu_frames: 0,
+            silent_run: 0,
+            idle_after,
+        }
+    }
+
+    /// Drops all but the newest `LIVE_CUSHION` of what's buffered, in whole
+    /// frames so the channels stay in step. Only called between frames.
+    fn catch_up(&mut self) {
+        let cushion = (self.channels as u128 * self.sample_rate as u128 * LIVE_CUSHION.as_millis()
+            / 1000) as usize;
+        let excess = self.buffer.len().saturating_sub(cushion);
+        self.buffer
+            .drain(..excess - excess % self.channels.max(1) as usize);
+    }
+
+    /// Moves everything that has arrived into the buffer without blocking,
+    /// dropping the oldest if that takes it past the cap. Returns false once
+    /// the decoder has gone away or said it's done.
+    fn drain(&mut self) -> bool {
+        // read first, so whatever came before the end is still taken in
+        let ended = self.incoming.ended();
+        let connected = loop {
+            match self.incoming.chunks.try_recv() {
+                Ok(chunk) => {
+                    if !self.heard {
+                        self.heard = true;
+                        self.stats.audio_arrived();
+                    }
+                    self.buffer.extend(chunk.samples.iter());
+                }
+                Err(mpsc::TryRecvError::Empty) => break !ended,
+                Err(mpsc::TryRecvError::Disconnected) => break false,
+            }
+        };
+        if let Some(cap) = self.cap {
+            // whole frames, so the next sample is still for the same channel
+            let excess = self.buffer.len().saturating_sub(cap);
+            let excess = excess - excess % self.channels.max(1) as usize;
+            if excess > 0 {
+                self.buffer.drain(..excess);
+                debug!(dropped = excess, "buffer over its cap");
+            }
+        }
+        connected
+    }
+
+    fn next_sample(&mut self) -> Option<f32> {
+        let connected = self.drain();
+        if self.frame_pos == 0 && self.stats.flush.swap(false, Ordering::Relaxed) {
+            self.catch_up();
+        }
+        self.stats
+            .buffered
+            .store(self.buffer.len() as u64, Ordering::Relaxed);
+
+        // Play silence until the pre-buffer has filled up.
+        if self.prebuffering {
+            if connected && self.buffer.len() < self.target {
+                return Some(0.0);
+            }
+            self.prebuffering = false;
+            self.stats.prebuffering.store(false, Ordering::Relaxed);
+        }
+
+        self.stats.played_samples.fetch_add(1, Ordering::Relaxed);
+        if let Some(s) = self.buffer.pop_front() {
+            self.starved = false;
+            self.watch_idle(s.unsigned_abs() <= SILENCE_LEVEL);
+            return Some(s as f32 / 32768.0);
+        }
+        if !connected {
+            return None;
+        }
+
+        // Ran dry: fill in with silence rather than holding up the audio thread.
+        if !self.starved {
+            self.starved = true;
+            let underruns = self.stats.underruns.fetch_add(1, Ordering::Relaxed) + 1;
+            debug!(underruns, "underrun");
+        }
+        self.stats.silent_samples.fetch_add(1, Ordering::Relaxed);
+        self.watch_idle(true);
+        Some(0.0)
+    }
+
+    /// Counts the samples of silence in a row, whether the stream's own or
+    /// filling in for audio that hasn't come, and says when it's gone idle.
+    fn watch_idle(&mut self, silent: bool) {
+        let Some(idle_after) = self.idle_after else {
+            return;
+        };
+        if !silent {
+            if self.silent_run >= idle_after {
+                self.stats.idle.store(false, Ordering::Relaxed);
+            }
+            self.silent_run = 0;
+            return;
+        }
+        self.silent_run += 1;
+        if self.silent_run == idle_after {
+            debug!("stream idle");
+            self.stats.idle.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Copies what's being played into `recent` as mono, in batches so the
+    /// audio thread isn't taking a lock for every sample.
+    fn tee(&mut self, sample: f32) {
+        if let Some(sum) = self.vu_sums.get_mut(self.frame_pos as usize) {
+            *sum += sample * sample;
+            let peak = &mut self.vu_peaks[self.frame_pos as usize];
+            *peak = peak.max(sample.abs());
+        }
+        self.frame_sum += sample;
+        self.frame_pos += 1;
+        if self.frame_pos < self.channels {
+            return;
+        }
+        self.tap.push(self.frame_sum / self.channels as f32);
+        self.frame_sum = 0.0;
+        self.frame_pos = 0;
+        self.measure();
+
+        if self.tap.len() < TAP_BATCH {
+            return;
+        }
+        // never wait on the UI from the audio thread; try again next batch
+        if let Ok(mut recent) = self.recent.try_lock() {
+            recent.extend(self.tap.drain(..));
+            let excess = recent.len().saturating_sub(RECENT_SAMPLES);
+            recent.drain(..excess);
+        }
+    }
+
+    /// Feeds a finished block's RMS and peaks to the VU levels; called once
+    /// per frame.
+    fn measure(&mut self) {
+        self.vu_frames += 1;
+        if self.vu_frames < VU_BLOCK {
+            return;
+        }
+
+        let left = (self.vu_sums[0] / VU_BLOCK as f32).sqrt();
+        let right = if self.channels > 1 {
+            (self.vu_sums[1] / VU_BLOCK as f32).sqrt()
+        } else {
+            left
+        };
+        let block_secs = VU_BLOCK as f32 / self.sample_rate as f32;
+        let weight = 1.0 - (-block_secs / VU_SETTLE_SECS).exp();
+        self.levels.follow(left, right, weight);
+        let peaks = if self.channels > 1 {
+            self.vu_peaks
+        } else {
+            [self.vu_peaks[0]; 2]
+        };
+        self.levels
+            .hold(peaks, 10f32.powf(-PEAK_FALL_DB_PER_SEC * block_secs / 20.0));
+
+        self.vu_sums = [0.0; 2];
+        self.vu_peaks = [0.0; 2];
+        self.vu_frames = 0;
+    }
+}
+
+impl Iterator for FfmpegSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.next_sample()?;
+        self.tee(sample);
+        Some(sample)
+    }
+}
+
+impl Source for FfmpegSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
     fn total_duration(&self) -> Option<std::time::Duration> {
         None // live stream
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn s16le_samples_survive_odd_splits() {
+        // 1, -1, i16::MAX, i16::MIN
+        let bytes = [0x01, 0x00, 0xff, 0xff, 0xff, 0x7f, 0x00, 0x80];
+        let expected = [1, -1, i16::MAX, i16::MIN];
+
+        let mut carry = None;
+        assert_eq!(decode_s16le(&mut carry, &bytes), expected);
+        assert_eq!(carry, None);
+
+        let mut carry = None;
+        let mut samples = decode_s16le(&mut carry, &bytes[..3]);
+        assert_eq!(carry, Some(0xff));
+        samples.extend(decode_s16le(&mut carry, &bytes[3..4]));
+        samples.extend(decode_s16le(&mut carry, &bytes[4..]));
+        assert_eq!(samples, expected);
+        assert_eq!(carry, None);
+    }
+
+    #[test]
+    fn stream_line_fields() {
+        assert_eq!(
+            parse_stream_line(
+                "  Stream #0:0: Audio: mp3 (mp3float), 44100 Hz, stereo, fltp, 320 kb/s"
+            ),
+            Some(StreamInfo {
+                codec: "mp3".to_string(),
+                bitrate_kbps: Some(320),
+                sample_rate: Some(44100),
+                channels: Some("stereo".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_stream_line("Stream #0:0: Audio: vorbis, 48000 Hz, mono, fltp"),
+            Some(StreamInfo {
+                codec: "vorbis".to_string(),
+                bitrate_kbps: None,
+                sample_rate: Some(48000),
+                channels: Some("mono".to_string()),
+            })
+        );
+        assert_eq!(parse_stream_line("Input #0, mp3, from 'pipe:0':"), None);
+    }
+
+    /// Hands out its bytes in reads of the given sizes, then the rest.
+    struct Chunked<'a> {
+        bytes: &'a [u8],
+        sizes: std::vec::IntoIter<usize>,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let size = self.sizes.next().unwrap_or(self.bytes.len());
+            let n = size.min(buf.len()).min(self.bytes.len());
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    fn incoming(chunks: mpsc::Receiver<Chunk>) -> Incoming {
+        Incoming {
+            chunks,
+            ended: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn source_ends_with_the_stream_even_while_the_feed_is_open() {
+        let (tx, rx) = mpsc::sync_channel(2);
+        let ended = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(PlaybackStats::default());
+        let mut source = FfmpegSource::new(
+            Incoming {
+                chunks: rx,
+                ended: ended.clone(),
+            },
+            2,
+            10,
+            stats.clone(),
+            Buffering {
+                prebuffer: Duration::ZERO,
+                max: Duration::ZERO,
+            },
+            RecentSamples::default(),
+            Arc::default(),
+        );
+        let feed = Feed::new(tx, ended, Arc::default(), 2, 10, false);
+        assert!(feed.send(&[1, 2]));
+        assert_eq!(source.next(), Some(1.0 / 32768.0));
+        assert_eq!(source.next(), Some(2.0 / 32768.0));
+        // ran dry while the stream is still going: silence
+        assert_eq!(source.next(), Some(0.0));
+
+        // the reader hits EOF, but the feed hasn't been dropped yet
+        let mut samples = SampleReader::new(&[3u8, 0, 4, 0][..]);
+        samples.run(|batch| feed.send(batch));
+        feed.end();
+        let played: Vec<f32> = source.by_ref().take(10).collect();
+        assert_eq!(played, [3.0 / 32768.0, 4.0 / 32768.0]);
+        assert_eq!(source.next(), None);
+        assert_eq!(stats.underruns.load(Ordering::SeqCst), 1);
+        drop(feed);
+    }
+
+    #[test]
+    fn long_silence_makes_the_stream_idle() {
+        let (tx, rx) = mpsc::sync_channel(4);
+        let stats = Arc::new(PlaybackStats {
+            idle_after_ms: AtomicU64::new(1000),
+            ..Default::default()
+        });
+        // at 10 Hz mono a second is 10 samples
+        let mut source = FfmpegSource::new(
+            incoming(rx),
+            1,
+            10,
+            stats.clone(),
+            Buffering::default(),
+            RecentSamples::default(),
+            Arc::default(),
+        );
+        let send = |samples: Vec<i16>| {
+            tx.send(Chunk {
+                channels: 1,
+                sample_rate: 10,
+                samples: samples.into(),
+            })
+            .unwrap()
+        };
+        // quiet music, then near silence: 8 samples of it
+        send(vec![100, -50, 3, -2, 0, 0, 0, 0, 0, 0]);
+        source.by_ref().take(10).for_each(drop);
+        assert!(!stats.idle.load(Ordering::SeqCst));
+        // the stream stops sending, and the gap is filled with silence
+        source.next();
+        assert!(!stats.idle.load(Ordering::SeqCst));
+        source.next();
+        assert!(stats.idle.load(Ordering::SeqCst));
+
+        send(vec![500]);
+        source.next();
+        assert!(!stats.idle.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn catching_up_keeps_the_newest_whole_frames() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let stats = Arc::new(PlaybackStats::default());
+        // at 10 Hz stereo the cushion is 4 samples
+        let mut source = FfmpegSource::new(
+            incoming(rx),
+            2,
+            10,
+            stats.clone(),
+            Buffering::default(),
+            RecentSamples::default(),
+            Arc::default(),
+        );
+        let samples: Vec<i16> = (0..11).map(|i| i * 1000).collect();
+        tx.send(Chunk {
+            channels: 2,
+            sample_rate: 10,
+            samples: samples.into(),
+        })
+        .unwrap();
+        drop(tx);
+
+        let mut played = vec![source.next().unwrap()];
+        // asked for halfway through a frame, it waits for the next one
+        stats.flush.store(true, Ordering::SeqCst);
+        played.extend(source);
+        let expected: Vec<f32> = [0, 1, 6, 7, 8, 9, 10]
+            .iter()
+            .map(|i| (i * 1000) as f32 / 32768.0)
+            .collect();
+        assert_eq!(played, expected);
+    }
+
+    #[test]
+    fn buffer_past_the_cap_loses_its_oldest_frames() {
+        let (tx, rx) = mpsc::sync_channel(2);
+        let stats = Arc::new(PlaybackStats::default());
+        // at 10 Hz stereo 300 ms is 6 samples
+        let mut source = FfmpegSource::new(
+            incoming(rx),
+            2,
+            10,
+            stats.clone(),
+            Buffering {
+                prebuffer: Duration::ZERO,
+                max: Duration::from_millis(300),
+            },
+            RecentSamples::default(),
+            Arc::default(),
+        );
+        let send = |samples: Vec<i16>| {
+            tx.send(Chunk {
+                channels: 2,
+                sample_rate: 10,
+                samples: samples.into(),
+            })
+            .unwrap()
+        };
+        send((0..12).collect());
+
+        let mut played = vec![source.next().unwrap()];
+        assert_eq!(stats.buffered.load(Ordering::SeqCst), 6);
+        assert_eq!(stats.buffer_rate.load(Ordering::SeqCst), 20);
+        // halfway through a frame, the next sample is still the right channel
+        send((100..104).collect());
+        drop(tx);
+        played.extend(source);
+        let expected: Vec<f32> = [6, 9, 10, 11, 100, 101, 102, 103]
+            .iter()
+            .map(|i| *i as f32 / 32768.0)
+            .collect();
+        assert_eq!(played, expected);
+    }
+
+    #[test]
+    fn peaks_fall_back_but_the_loudest_sample_waits_to_be_seen() {
+        let (tx, rx) = mpsc::sync_channel(2);
+        let levels = Arc::new(Levels::default());
+        // at this rate a VU block is a tenth of a second
+        let rate = VU_BLOCK * 10;
+        let source = FfmpegSource::new(
+            incoming(rx),
+            2,
+            rate,
+            Arc::default(),
+            Buffering {
+                prebuffer: Duration::ZERO,
+                max: Duration::ZERO,
+            },
+            RecentSamples::default(),
+            levels.clone(),
+        );
+        let mut block = vec![0i16; VU_BLOCK as usize * 2];
+        block[0] = i16::MIN;
+        block[1] = 8192;
+        for samples in [block, vec![0; VU_BLOCK as usize * 2]] {
+            tx.send(Chunk {
+                channels: 2,
+                sample_rate: rate,
+                samples: samples.into(),
+            })
+            .unwrap();
+        }
+        drop(tx);
+        assert_eq!(source.count(), VU_BLOCK as usize * 4);
+
+        assert_eq!(levels.take_loudest(), 1.0);
+        assert_eq!(levels.take_loudest(), 0.0);
+        // down 2 dB after a block of silence
+        let (left, right) = levels.peaks();
+        assert!((left - 0.794).abs() < 0.001, "{}", left);
+        assert!((right - 0.25 * 0.794).abs() < 0.001, "{}", right);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shutting_down_reaps_the_decoder() {
+        let child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = child.id() as libc::pid_t;
+        let slot = Arc::new(Mutex::new(Some(child)));
+
+        reap(&slot);
+        assert!(slot.lock().unwrap().is_none());
+        // killed and waited for, so there's no such process any more
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+        reap(&slot);
+    }
+
+    #[test]
+    fn missing_ffmpeg_is_told_apart() {
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(matches!(
+            StartError::spawning_ffmpeg(missing),
+            StartError::FfmpegMissing
+        ));
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            StartError::spawning_ffmpeg(denied),
+            StartError::Decoder(_)
+        ));
+    }
+
+    #[test]
+    fn no_samples_lost_between_reads() {
+        let expected: Vec<i16> = (-500..500).map(|i| i * 37).collect();
+        let bytes: Vec<u8> = expected.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let reader = Chunked {
+            bytes: &bytes,
+            sizes: vec![3, 2, 1, 3].into_iter(),
+        };
+
+        // stopping after every batch loses nothing either
+        let mut samples = Vec::new();
+        let mut reader = SampleReader::new(reader);
+        for _ in 0..3 {
+            reader.run(|batch| {
+                samples.extend_from_slice(batch);
+                false
+            });
+        }
+        reader.run(|batch| {
+            samples.extend_from_slice(batch);
+            true
+        });
+        assert_eq!(samples, expected);
+    }
+
+    /// Plays sinks into nothing, pulling audio about as fast as a device
+    /// would, until it's dropped.
+    #[derive(Default)]
+    struct Nowhere {
+        gone: Arc<AtomicBool>,
+    }
+
+    impl Output for Nowhere {
+        fn sink(&self) -> Result<Sink, PlayError> {
+            let (sink, mut queue) = Sink::new_idle();
+            let gone = self.gone.clone();
+            thread::spawn(move || {
+                while !gone.load(Ordering::SeqCst) {
+                    queue.by_ref().take(TONE_BATCH).for_each(drop);
+                    thread::sleep(Duration::from_millis(10));
+                }
+            });
+            Ok(sink)
+        }
+    }
+
+    impl Drop for Nowhere {
+        fn drop(&mut self) {
+            self.gone.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // 10 ms of the tone: 8 kHz stereo
+    const TONE_RATE: u32 = 8000;
+    const TONE_BATCH: usize = 160;
+
+    /// A 440 Hz tone in place of a stream, made as it's played, until the
+    /// pipeline winds it down. Counts its runs in and out.
+    #[derive(Clone, Default)]
+    struct Tone {
+        started: Arc<AtomicU32>,
+        finished: Arc<AtomicU32>,
+    }
+
+    impl Decode for Tone {
+        fn run(&mut self, pipeline: &Pipeline) -> bool {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            let feed = pipeline.attach(2, TONE_RATE);
+            let mut frame = 0u32;
+            while pipeline.keep_going() {
+                let batch: Vec<i16> = (0..TONE_BATCH / 2)
+                    .flat_map(|_| {
+                        frame += 1;
+                        let t = frame as f32 / TONE_RATE as f32;
+                        let s = ((t * 440.0 * std::f32::consts::TAU).sin() * 8000.0) as i16;
+                        [s, s]
+                    })
+                    .collect();
+                if !feed.send(&batch) {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            feed.end();
+            self.finished.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    fn start_tone(config: &Config, tone: &Tone) -> PlayerControl {
+        let (titles, _) = watch::channel(None);
+        let (control, _) = start(
+            "tone",
+            config,
+            Backend::Symphonia,
+            Box::new(Nowhere::default()),
+            Box::new(tone.clone()),
+            titles,
+        )
+        .unwrap();
+        control
+    }
+
+    fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "timed out waiting for {}",
+                what
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Whether the sink is taking audio from the source.
+    fn advancing(control: &PlayerControl) -> bool {
+        let before = control.stats.played_samples.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(100));
+        control.stats.played_samples.load(Ordering::SeqCst) > before
+    }
+
+    #[test]
+    fn controls_work_without_a_device() {
+        let config = Config {
+            fade_ms: 0,
+            prebuffer_ms: 50,
+            default_volume: 0.8,
+            max_volume: 1.5,
+            ..Config::default()
+        };
+        let tone = Tone::default();
+        let control = start_tone(&config, &tone);
+        wait_for("the tone to play", || advancing(&control));
+        assert_eq!(control.connection_state(), ConnectionState::Connected);
+        assert_eq!(control.volume(), 0.8);
+
+        control.set_volume(0.5);
+        assert_eq!(control.volume(), 0.5);
+        control.set_volume(3.0);
+        assert_eq!(control.volume(), 1.5);
+        control.mute();
+        assert!(control.is_muted());
+        assert_eq!(control.volume(), 0.0);
+        control.unmute();
+        assert_eq!(control.volume(), 1.5);
+
+        control.pause();
+        assert!(control.is_paused());
+        // the sink notices within a few milliseconds
+        thread::sleep(Duration::from_millis(50));
+        assert!(!advancing(&control));
+        control.play();
+        assert!(!control.is_paused());
+        wait_for("playback to resume", || advancing(&control));
+        // paused without pause_disconnects, the tone kept its connection
+        assert_eq!(tone.started.load(Ordering::SeqCst), 1);
+
+        control.stop();
+        wait_for("the tone to stop", || {
+            tone.finished.load(Ordering::SeqCst) == 1
+        });
+        assert!(!advancing(&control));
+    }
+
+    #[test]
+    fn pausing_can_disconnect_until_play() {
+        let config = Config {
+            fade_ms: 0,
+            prebuffer_ms: 50,
+            pause_disconnects: true,
+            ..Config::default()
+        };
+        let tone = Tone::default();
+        let control = start_tone(&config, &tone);
+        wait_for("the tone to play", || advancing(&control));
+
+        control.pause();
+        wait_for("the tone to stop", || {
+            tone.finished.load(Ordering::SeqCst) == 1
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(tone.started.load(Ordering::SeqCst), 1);
+
+        control.play();
+        wait_for("the tone to start again", || {
+            tone.started.load(Ordering::SeqCst) == 2
+        });
+        wait_for("playback to resume", || advancing(&control));
+        assert_eq!(control.connection_state(), ConnectionState::Connected);
+    }
+
+    /// A stream that can't be reached.
+    struct Unreachable;
+
+    impl Decode for Unreachable {
+        fn run(&mut self, pipeline: &Pipeline) -> bool {
+            record(&pipeline.last_error, "connection refused");
+            false
+        }
+    }
+
+    #[test]
+    fn startup_waits_for_audio_or_gives_up() {
+        let urls = ["tone".to_string()];
+        let control = start_tone(&Config::default(), &Tone::default());
+        assert_eq!(control.first_that_plays(&urls).unwrap(), "tone");
+        assert!(control.stats.decoded.load(Ordering::SeqCst));
+
+        let (titles, _) = watch::channel(None);
+        let (control, _) = start(
+            "nowhere",
+            &Config::default(),
+            Backend::Symphonia,
+            Box::new(Nowhere::default()),
+            Box::new(Unreachable),
+            titles,
+        )
+        .unwrap();
+        let urls = ["nowhere/mp3".to_string(), "nowhere/ogg".to_string()];
+        match control.first_that_plays(&urls) {
+            Err(StartError::NoAudio(Some(e))) => assert_eq!(e, "connection refused"),
+            other => panic!("expected no audio, got {:?}", other),
+        }
+        assert_eq!(control.stats.failed_runs.load(Ordering::SeqCst), 2);
+    }
+}
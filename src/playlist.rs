@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use reqwest::{Client, Url};
+use std::time::Duration;
+
+const PLAYLIST_CONTENT_TYPES: &[&str] = &[
+    "audio/x-mpegurl",
+    "audio/mpegurl",
+    "audio/x-scpls",
+    "application/pls+xml",
+];
+
+// Playlists can point at other playlists; don't follow them forever.
+const MAX_DEPTH: usize = 3;
+
+/// Resolves `url` to an actual audio stream. Plain stream URLs are returned
+/// unchanged; `.m3u`/`.pls` playlists are fetched and their first entry used.
+pub async fn resolve(client: &Client, url: &str) -> Result<String> {
+    let mut current = url.to_string();
+
+    for _ in 0..MAX_DEPTH {
+        let resp = client
+            .get(&current)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await?;
+
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        if !has_playlist_extension(&current) && !is_playlist_content_type(&content_type) {
+            // Dropping the response closes the connection before the audio body is read.
+            return Ok(current);
+        }
+
+        if !resp.status().is_success() {
+            return Err(anyhow!("playlist request failed: {}", resp.status()));
+        }
+
+        let base = resp.url().clone();
+        let body = resp.text().await?;
+
+        // HLS playlists look like M3U but ffmpeg handles them natively.
+        if body.contains("#EXT-X-") {
+            return Ok(current);
+        }
+
+        let entry = if body
+            .trim_start()
+            .to_ascii_lowercase()
+            .starts_with("[playlist]")
+        {
+            parse_pls(&body)
+        } else {
+            parse_m3u(&body)
+        }
+        .ok_or_else(|| anyhow!("playlist at {} has no stream entries", current))?;
+
+        current = base.join(&entry).map(|u| u.to_string()).unwrap_or(entry);
+    }
+
+    Ok(current)
+}
+
+fn has_playlist_extension(url: &str) -> bool {
+    let path = Url::parse(url)
+        .map(|u| u.path().to_ascii_lowercase())
+        .unwrap_or_else(|_| url.to_ascii_lowercase());
+    path.ends_with(".m3u") || path.ends_with(".pls")
+}
+
+fn is_playlist_content_type(content_type: &str) -> bool {
+    PLAYLIST_CONTENT_TYPES
+        .iter()
+        .any(|t| content_type.starts_with(t))
+}
+
+/// First non-comment line of an M3U/EXTM3U playlist.
+fn parse_m3u(body: &str) -> Option<String> {
+    body.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|s| s.to_string())
+}
+
+/// Lowest-numbered `FileN=` entry of a PLS playlist.
+fn parse_pls(body: &str) -> Option<String> {
+    body.lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let index = key.trim().to_ascii_lowercase();
+            let index: u32 = index.strip_prefix("file")?.parse().ok()?;
+            Some((index, value.trim().to_string()))
+        })
+        .filter(|(_, value)| !value.is_empty())
+        .min_by_key(|(index, _)| *index)
+        .map(|(_, value)| value)
+}
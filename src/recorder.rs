@@ -0,0 +1,150 @@
+use crate::bus::SampleBus;
+use crate::metadata::NowPlaying;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// How often a recorder waiting for audio checks whether it should stop.
+const STOP_CHECK: Duration = Duration::from_millis(100);
+
+/// Writes everything published on the bus to a WAV file.
+pub struct Recorder {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl Recorder {
+    /// The file is created right away so a bad path is reported up front; the
+    /// WAV header follows once the first chunk tells us the format.
+    pub fn start(path: &Path, bus: &SampleBus) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+        let rx = bus.subscribe();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+
+        let thread = thread::spawn(move || {
+            let mut file = Some(BufWriter::new(file));
+            let mut writer = None;
+
+            while !stopped.load(Ordering::Relaxed) {
+                let chunk = match rx.recv_timeout(STOP_CHECK) {
+                    Ok(chunk) => chunk,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let writer = match &mut writer {
+                    Some(w) => w,
+                    None => {
+                        let Some(file) = file.take() else { break };
+                        let spec = hound::WavSpec {
+                            channels: chunk.channels,
+                            sample_rate: chunk.sample_rate,
+                            bits_per_sample: 16,
+                            sample_format: hound::SampleFormat::Int,
+                        };
+                        writer.insert(hound::WavWriter::new(file, spec)?)
+                    }
+                };
+
+                // a WAV file can't change format halfway through
+                let spec = writer.spec();
+                if chunk.channels != spec.channels || chunk.sample_rate != spec.sample_rate {
+                    continue;
+                }
+
+                let mut samples = writer.get_i16_writer(chunk.samples.len() as u32);
+                for &s in chunk.samples.iter() {
+                    samples.write_sample(s);
+                }
+                samples.flush()?;
+            }
+
+            if let Some(writer) = writer {
+                writer.finalize()?;
+            }
+            Ok(())
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            stop,
+            thread,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops recording and waits for the WAV header to be finalized.
+    pub fn finish(self) -> Result<PathBuf> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread
+            .join()
+            .map_err(|_| anyhow::anyhow!("recorder thread panicked"))?
+            .with_context(|| format!("Failed to write recording {}", self.path.display()))?;
+        Ok(self.path)
+    }
+}
+
+/// A file in `dir` named after the track, e.g. "Artist - Title.wav", with a
+/// number added if that's taken.
+pub fn recording_path(dir: &Path, track: &NowPlaying) -> PathBuf {
+    let name = match (&track.artist, &track.title) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (Some(only), None) | (None, Some(only)) => only.clone(),
+        (None, None) => "Plaza Radio".to_string(),
+    };
+    // characters that aren't allowed in file names somewhere
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+
+    let mut path = dir.join(format!("{}.wav", name));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{} ({}).wav", name, n));
+        n += 1;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_named_after_the_track() {
+        let dir = std::env::temp_dir().join(format!("plaza_rec_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let track = NowPlaying {
+            artist: Some("AC/DC".to_string()),
+            title: Some("What? Now".to_string()),
+            ..Default::default()
+        };
+
+        let first = recording_path(&dir, &track);
+        assert_eq!(first, dir.join("AC_DC - What_ Now.wav"));
+        File::create(&first).unwrap();
+        assert_eq!(
+            recording_path(&dir, &track),
+            dir.join("AC_DC - What_ Now (2).wav")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+use crate::config::{Config, LastFm};
+use crate::metadata::NowPlaying;
+use anyhow::{anyhow, bail, Result};
+use reqwest::Client;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
+use tracing::warn;
+
+const API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// Last.fm counts a long track as heard after four minutes, however much of
+/// it is left.
+const SCROBBLE_BY: Duration = Duration::from_secs(240);
+
+/// Last.fm's desktop login: the user allows access in the browser, and the
+/// session key that comes back is saved to the config file at `config_path`.
+pub async fn authenticate(
+    client: &Client,
+    config: &Config,
+    config_path: Option<&Path>,
+) -> Result<()> {
+    let config_path = config_path.ok_or_else(|| anyhow!("No config directory on this system"))?;
+    let lastfm = config
+        .lastfm
+        .as_ref()
+        .filter(|l| !l.api_key.is_empty() && !l.api_secret.is_empty())
+        .ok_or_else(|| anyhow!("Set api_key and api_secret under [lastfm] in the config first"))?;
+
+    let json = call(client, lastfm, "auth.getToken", &[]).await?;
+    let token = json["token"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Last.fm sent no token"))?;
+
+    println!(
+        "Allow access at https://www.last.fm/api/auth/?api_key={}&token={}",
+        lastfm.api_key, token
+    );
+    println!("then press Enter here.");
+    std::io::stdin().lock().read_line(&mut String::new())?;
+
+    let json = call(client, lastfm, "auth.getSession", &[("token", token)]).await?;
+    let key = json["session"]["key"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Last.fm sent no session key"))?;
+    Config::save_lastfm_session(config_path, key)?;
+    println!("Logged in; session key saved to {}", config_path.display());
+    Ok(())
+}
+
+/// Follows the now-playing channel: each new track is sent as Last.fm's
+/// "now playing", and scrobbled once it has played for `scrobble_after`'s
+/// share of it.
+pub async fn run(
+    client: Client,
+    lastfm: LastFm,
+    min_listen: Duration,
+    mut rx: watch::Receiver<NowPlaying>,
+) {
+    // the track playing, when it started (Unix seconds, and here), and when
+    // it's due
+    let mut current: Option<(NowPlaying, u64)> = None;
+    let mut heard_at = Instant::now();
+    let mut due: Option<Instant> = None;
+
+    loop {
+        tokio::select! {
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let np = rx.borrow_and_update().clone();
+                if let Some((track, _)) = current.as_mut().filter(|(track, _)| track.same_track(&np)) {
+                    // the API can say how long it is after the stream's title came in
+                    if due.is_some() && track.progress.is_none() && np.progress.is_some() {
+                        due = Some(heard_at + scrobble_after(&np, min_listen));
+                        *track = np;
+                    }
+                    continue;
+                }
+
+                current = None;
+                due = None;
+                // talk and station IDs aren't songs to scrobble
+                if np.is_live() {
+                    continue;
+                }
+                let (Some(artist), Some(title)) = (&np.artist, &np.title) else {
+                    continue;
+                };
+                let params = [("artist", artist.as_str()), ("track", title.as_str())];
+                if let Err(e) = call(&client, &lastfm, "track.updateNowPlaying", &params).await {
+                    warn!("Last.fm now playing update failed: {:#}", e);
+                }
+
+                let started = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                heard_at = Instant::now();
+                due = Some(heard_at + scrobble_after(&np, min_listen));
+                current = Some((np, started));
+            }
+            // the deadline is only looked at while there is one
+            _ = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                due = None;
+                let Some((track, started)) = &current else {
+                    continue;
+                };
+                let (Some(artist), Some(title)) = (&track.artist, &track.title) else {
+                    continue;
+                };
+                let timestamp = started.to_string();
+                let params = [
+                    ("artist", artist.as_str()),
+                    ("track", title.as_str()),
+                    ("timestamp", timestamp.as_str()),
+                ];
+                if let Err(e) = call(&client, &lastfm, "track.scrobble", &params).await {
+                    warn!("Last.fm scrobble failed: {:#}", e);
+                }
+            }
+        }
+    }
+}
+
+/// How long `np` has to play to be scrobbled: half of it or four minutes,
+/// whichever is sooner, as Last.fm asks, when its length is known, but never
+/// less than `min_listen`, which is all there is to go on otherwise.
+fn scrobble_after(np: &NowPlaying, min_listen: Duration) -> Duration {
+    match np.progress {
+        Some(progress) => (progress.duration / 2).min(SCROBBLE_BY).max(min_listen),
+        None => min_listen,
+    }
+}
+
+/// Signs and sends one API call, with the session key if there is one.
+async fn call(
+    client: &Client,
+    lastfm: &LastFm,
+    method: &str,
+    params: &[(&str, &str)],
+) -> Result<Value> {
+    let mut params: BTreeMap<&str, &str> = params.iter().copied().collect();
+    params.insert("method", method);
+    params.insert("api_key", &lastfm.api_key);
+    if let Some(sk) = &lastfm.session_key {
+        params.insert("sk", sk);
+    }
+    let api_sig = sign(&params, &lastfm.api_secret);
+
+    let mut form: Vec<(&str, &str)> = params.into_iter().collect();
+    form.push(("api_sig", &api_sig));
+    // not part of the signature
+    form.push(("format", "json"));
+
+    let body = client
+        .post(API_URL)
+        .form(&form)
+        .send()
+        .await?
+        .text()
+        .await?;
+    let json: Value = serde_json::from_str(&body)?;
+    if let Some(code) = json.get("error") {
+        bail!(
+            "error {}: {}",
+            code,
+            json["message"].as_str().unwrap_or("no message")
+        );
+    }
+    Ok(json)
+}
+
+/// Last.fm's `api_sig`: every parameter's name and value, in name order,
+/// followed by the secret, hashed with MD5.
+fn sign(params: &BTreeMap<&str, &str>, secret: &str) -> String {
+    let mut text = String::new();
+    for (name, value) in params {
+        text.push_str(name);
+        text.push_str(value);
+    }
+    text.push_str(secret);
+    format!("{:x}", md5::compute(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Progress;
+
+    #[test]
+    fn signature_covers_sorted_params_and_secret() {
+        let params = BTreeMap::from([
+            ("method", "auth.getSession"),
+            ("api_key", "key"),
+            ("token", "tok"),
+        ]);
+        // md5("api_keykeymethodauth.getSessiontokentoksecret")
+        assert_eq!(sign(&params, "secret"), "04e870be4bb79756721b7bc1937fe83d");
+    }
+
+    #[test]
+    fn half_the_track_but_never_less_than_the_minimum() {
+        let min_listen = Duration::from_secs(30);
+        let mut np = NowPlaying::default();
+        assert_eq!(scrobble_after(&np, min_listen), min_listen);
+
+        np.progress = Some(Progress {
+            duration: Duration::from_secs(212),
+            elapsed: Duration::from_secs(41),
+            at: std::time::Instant::now(),
+        });
+        assert_eq!(scrobble_after(&np, min_listen), Duration::from_secs(106));
+
+        np.progress = Some(Progress {
+            duration: Duration::from_secs(40),
+            elapsed: Duration::ZERO,
+            at: std::time::Instant::now(),
+        });
+        assert_eq!(scrobble_after(&np, min_listen), min_listen);
+
+        // a long mix counts after four minutes
+        np.progress = Some(Progress {
+            duration: Duration::from_secs(3600),
+            elapsed: Duration::ZERO,
+            at: std::time::Instant::now(),
+        });
+        assert_eq!(scrobble_after(&np, min_listen), Duration::from_secs(240));
+        assert_eq!(
+            scrobble_after(&np, Duration::from_secs(300)),
+            Duration::from_secs(300)
+        );
+    }
+}
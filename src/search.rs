@@ -0,0 +1,109 @@
+use crate::metadata::NowPlaying;
+use anyhow::{Context, Result};
+use std::process::{Command, Stdio};
+use tracing::warn;
+
+/// Where o goes when there's no `search_url` in the config.
+pub const DEFAULT_URL: &str = "https://www.youtube.com/results?search_query={query}";
+
+/// `template` with "artist title" (whichever is known) percent-encoded in
+/// place of `{query}`; None when neither is.
+pub fn url(template: &str, np: &NowPlaying) -> Option<String> {
+    let query = [&np.artist, &np.title]
+        .into_iter()
+        .flatten()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if query.is_empty() {
+        return None;
+    }
+    Some(template.replace("{query}", &encode(&query)))
+}
+
+/// Everything but the unreserved characters, byte by byte, so it's safe in
+/// a path as well as a query.
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Hands `url` to the desktop's default browser. Only starting the opener
+/// happens here; it's waited on in the background so a slow one can't hold
+/// up the UI.
+pub fn open(url: &str) -> Result<()> {
+    let mut command = opener(url);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", command.get_program()))?;
+    std::thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => warn!(%status, "browser opener failed"),
+        Err(e) => warn!(error = %e, "browser opener failed"),
+        Ok(_) => {}
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("open");
+    command.arg(url);
+    command
+}
+
+/// `start`, through cmd. The URL is quoted as it's passed on, or cmd would
+/// take an `&` in it as the end of the command; the empty title is there so
+/// `start` doesn't take the quoted URL for one.
+#[cfg(windows)]
+fn opener(url: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let mut command = Command::new("cmd");
+    command.raw_arg(format!("/C start \"\" \"{}\"", url));
+    command
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn opener(url: &str) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(url);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_goes_into_the_template() {
+        let np = NowPlaying {
+            artist: Some("マクロスMACROSS 82-99".to_string()),
+            title: Some("Miss Lonely & Blue".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            url("https://bandcamp.com/search?q={query}", &np).unwrap(),
+            "https://bandcamp.com/search?q=%E3%83%9E%E3%82%AF%E3%83%AD%E3%82%B9MACROSS%2082-99%20Miss%20Lonely%20%26%20Blue"
+        );
+
+        let title_only = NowPlaying {
+            title: Some("Blue".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            url(DEFAULT_URL, &title_only).unwrap(),
+            "https://www.youtube.com/results?search_query=Blue"
+        );
+        assert_eq!(url(DEFAULT_URL, &NowPlaying::default()), None);
+    }
+}
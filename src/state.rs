@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// What's carried over from one run to the next.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub volume: f32,
+    pub muted: bool,
+}
+
+/// Where the state is kept, next to the config.
+pub fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("plaza_radio_tui").join("state.json"))
+}
+
+/// The state the last run left, if it left one that can be read.
+pub fn load(path: &Path) -> Option<State> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(path = %path.display(), "failed to read the saved state: {}", e);
+            return None;
+        }
+    };
+    match serde_json::from_str::<State>(&text) {
+        Ok(state) if state.volume.is_finite() => Some(state),
+        Ok(_) | Err(_) => {
+            warn!(path = %path.display(), "ignoring a corrupt saved state");
+            None
+        }
+    }
+}
+
+pub fn save(path: &Path, state: &State) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, serde_json::to_string(state)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_survives_a_restart_but_not_corruption() {
+        let dir = std::env::temp_dir().join(format!("plaza_state_test_{}", std::process::id()));
+        let path = dir.join("state.json");
+
+        assert_eq!(load(&path), None);
+        let state = State {
+            volume: 0.8,
+            muted: true,
+        };
+        save(&path, &state).unwrap();
+        assert_eq!(load(&path), Some(state));
+
+        std::fs::write(&path, "{\"volume\": 0.").unwrap();
+        assert_eq!(load(&path), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::color::ColorDepth;
+use crate::config::ThemeConfig;
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// A color as written in the config: one of the 16 terminal color names
+/// ("magenta", "light-cyan", ...) or "#rrggbb".
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ThemeColor(pub Color);
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+            .map(ThemeColor)
+            .map_err(|_| format!("not a color name or #rrggbb: {:?}", s))
+    }
+}
+
+/// The built-in palettes a `[theme]` starts from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemePreset {
+    /// Magenta and cyan.
+    #[default]
+    Plaza,
+    /// Warm pinks and oranges; needs truecolor.
+    Sunset,
+    /// Greys only.
+    Mono,
+}
+
+/// What the UI is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    /// The info panel's border and the help's.
+    pub border: Color,
+    pub art_border: Color,
+    /// The track's title and artist.
+    pub title: Color,
+    /// "Title:", "Artist:" and the visualizer headings.
+    pub label: Color,
+    pub status_playing: Color,
+    pub status_paused: Color,
+    pub volume_label: Color,
+    pub waveform: Color,
+    /// The logo shown without a cover fades from the one to the other.
+    pub art_gradient_start: Color,
+    pub art_gradient_end: Color,
+}
+
+impl Theme {
+    pub fn preset(preset: ThemePreset) -> Self {
+        match preset {
+            ThemePreset::Plaza => Theme {
+                border: Color::Cyan,
+                art_border: Color::Magenta,
+                title: Color::White,
+                label: Color::Cyan,
+                status_playing: Color::Green,
+                status_paused: Color::Yellow,
+                volume_label: Color::Magenta,
+                waveform: Color::Reset,
+                art_gradient_start: Color::Magenta,
+                art_gradient_end: Color::Cyan,
+            },
+            ThemePreset::Sunset => Theme {
+                border: Color::Rgb(0xff, 0x8c, 0x61),
+                art_border: Color::Rgb(0xce, 0x4a, 0x7e),
+                title: Color::Rgb(0xff, 0xe8, 0xd6),
+                label: Color::Rgb(0xfa, 0xa2, 0x75),
+                status_playing: Color::Rgb(0xff, 0xd1, 0x66),
+                status_paused: Color::Rgb(0xce, 0x4a, 0x7e),
+                volume_label: Color::Rgb(0xce, 0x4a, 0x7e),
+                waveform: Color::Rgb(0xff, 0x8c, 0x61),
+                art_gradient_start: Color::Rgb(0xff, 0xd1, 0x66),
+                art_gradient_end: Color::Rgb(0x7b, 0x2c, 0xbf),
+            },
+            ThemePreset::Mono => Theme {
+                border: Color::Gray,
+                art_border: Color::DarkGray,
+                title: Color::White,
+                label: Color::Gray,
+                status_playing: Color::White,
+                status_paused: Color::Gray,
+                volume_label: Color::Gray,
+                waveform: Color::Gray,
+                art_gradient_start: Color::White,
+                art_gradient_end: Color::DarkGray,
+            },
+        }
+    }
+
+    /// The preset named in the config with its colors swapped in.
+    pub fn from_config(config: &ThemeConfig) -> Self {
+        let mut theme = Self::preset(config.preset);
+        for (color, set) in [
+            (&mut theme.border, config.border),
+            (&mut theme.art_border, config.art_border),
+            (&mut theme.title, config.title),
+            (&mut theme.label, config.label),
+            (&mut theme.status_playing, config.status_playing),
+            (&mut theme.status_paused, config.status_paused),
+            (&mut theme.volume_label, config.volume_label),
+            (&mut theme.waveform, config.waveform),
+            (&mut theme.art_gradient_start, config.art_gradient_start),
+            (&mut theme.art_gradient_end, config.art_gradient_end),
+        ] {
+            if let Some(ThemeColor(set)) = set {
+                *color = set;
+            }
+        }
+        theme
+    }
+
+    /// Every color brought down to what `depth` has.
+    pub fn adapted(self, depth: ColorDepth) -> Self {
+        Theme {
+            border: depth.adapt(self.border),
+            art_border: depth.adapt(self.art_border),
+            title: depth.adapt(self.title),
+            label: depth.adapt(self.label),
+            status_playing: depth.adapt(self.status_playing),
+            status_paused: depth.adapt(self.status_paused),
+            volume_label: depth.adapt(self.volume_label),
+            waveform: depth.adapt(self.waveform),
+            // mixed first, in `art_gradient`, then brought down
+            ..self
+        }
+    }
+
+    /// Row `row` of `rows` of the logo. Between two hex colors it's a smooth
+    /// fade; named colors can't be mixed, so it's the one then the other.
+    pub fn art_gradient(&self, row: usize, rows: usize) -> Color {
+        let t = row as f32 / rows.saturating_sub(1).max(1) as f32;
+        match (self.art_gradient_start, self.art_gradient_end) {
+            (Color::Rgb(r1, g1, b1), Color::Rgb(r2, g2, b2)) => {
+                let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+                Color::Rgb(mix(r1, r2), mix(g1, g2), mix(b1, b2))
+            }
+            (start, _) if t < 0.5 => start,
+            (_, end) => end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_colors_override_the_preset() {
+        let config: ThemeConfig = toml::from_str(
+            r##"
+            preset = "mono"
+            border = "light-magenta"
+            art_gradient_start = "#000000"
+            art_gradient_end = "#ff8000"
+            "##,
+        )
+        .unwrap();
+        let theme = Theme::from_config(&config);
+        assert_eq!(theme.border, Color::LightMagenta);
+        assert_eq!(theme.title, Theme::preset(ThemePreset::Mono).title);
+        assert_eq!(theme.art_gradient(0, 5), Color::Rgb(0, 0, 0));
+        assert_eq!(theme.art_gradient(2, 5), Color::Rgb(128, 64, 0));
+        assert_eq!(theme.art_gradient(4, 5), Color::Rgb(255, 128, 0));
+
+        assert!(toml::from_str::<ThemeConfig>(r#"title = "chartreuse""#).is_err());
+    }
+}
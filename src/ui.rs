@@ -1,271 +1,1142 @@
-use crate::metadata::NowPlaying;
-use crate::player::PlayerControl;
-use anyhow::Result;
-use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode};
+use crate::art;
+use crate::color::ColorDepth;
+use crate::config::{
+    ClockFormat, Config, LayoutMode, Station, TrackAlert, Visualizer, VolumeStyle,
+};
+use crate::eq;
+use crate::favorites;
+use crate::history::{self, History};
+use crate::keymap::{self, Action, Keymap};
+use crate::metadata::{MetadataSource, NowPlaying, Subscription};
+use crate::mpris::{self, Remote};
+use crate::player::{ConnectionState, PlayerControl, RecentSamples, SinkInfo, StreamInfo};
+use crate::playlist;
+use crate::recorder;
+use crate::search;
+use crate::state;
+use crate::theme::Theme;
+use anyhow::{Context, Result};
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent, KeyEventKind,
+};
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
 };
+use futures::StreamExt;
+use image::DynamicImage;
 use rand::Rng;
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, BorderType, Borders, Paragraph},
-    Terminal,
+    widgets::{
+        block::{Position, Title},
+        Block, BorderType, Borders, Clear, Paragraph,
+    },
+    Frame, Terminal,
 };
 use reqwest::Client;
-use std::io::stdout;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::{self, stdout, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::watch;
+use tokio::sync::{mpsc, watch};
+
+// Terminals narrower or shorter than this always get the art-only layout.
+const ART_ONLY_BELOW: (u16, u16) = (60, 16);
+
+// Narrower than this, the art goes above the info rather than beside it, as
+// long as there's the height for both.
+const STACK_BELOW_WIDTH: u16 = 80;
+const STACKED_MIN_HEIGHT: u16 = 28;
+
+// Art is left out of areas smaller than this rather than cut off mid-glyph.
+const ART_HIDDEN_BELOW: (u16, u16) = (12, 5);
+// A `logo_file` is cut down to this many columns and rows; the panel crops
+// it further.
+const MAX_LOGO: (usize, usize) = (200, 100);
+
+// How much of why the stream is offline fits on the status line.
+const LAST_ERROR_SHOWN: usize = 60;
+
+// Below this share of the pre-buffer, playback is shown as buffering.
+const LOW_BUFFER: f32 = 0.25;
+
+// The waveform and spectrum get a bar per column of the panel, within these.
+const WAVE_BARS_MIN: usize = 10;
+const WAVE_BARS_MAX: usize = 120;
+const FFT_SIZE: usize = 1024;
+
+// Sleep timer choices, and how long it takes to fade out once it's up.
+const SLEEP_PRESETS_MIN: [u64; 3] = [15, 30, 60];
+const SLEEP_FADE: Duration = Duration::from_secs(10);
+const SLEEP_FADE_STEP: Duration = Duration::from_millis(100);
+
+// How long a notice (e.g. where a recording was saved) stays up.
+const NOTICE_SHOWN: Duration = Duration::from_secs(5);
+
+// Frame rate while something on screen moves, and how often to refresh
+// otherwise (relative times, and what the player thread changed).
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+// How long the borders flash for when a new track starts.
+const TRACK_FLASH: Duration = Duration::from_millis(300);
+// A title too long for the compact layout moves along a character this often.
+const SCROLL_STEP: Duration = Duration::from_millis(250);
+
+// The splash's spinner, a frame per step, and the longest the splash waits
+// for a track once the audio is there, so a slow API can't keep it up.
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_STEP: Duration = Duration::from_millis(100);
+const SPLASH_MAX: Duration = Duration::from_secs(5);
+
+// How fast the waveform moves in radians a second, and how much of its phase
+// is left after a second of being paused, however often it's redrawn.
+const WAVE_SPEED: f32 = 2.0;
+const WAVE_DECAY: f32 = 0.6;
+
+// The volume bar stretches with the panel too, leaving room for the
+// "│ +NN% 🔊" around it.
+const VOLUME_BAR_MIN: usize = 10;
+const VOLUME_BAR_MAX: usize = 60;
+const VOLUME_BAR_EXTRAS: usize = 11;
+
+// The VU bars span this many dBFS below full scale, and peaks stay put this long.
+const VU_WIDTH: usize = 32;
+const VU_FLOOR_DB: f32 = -48.0;
+const VU_PEAK_HOLD: Duration = Duration::from_millis(1500);
+// A sample this loud after the volume is clipped, and CLIP stays lit this long.
+const CLIP_LEVEL: f32 = 0.999;
+const CLIP_HOLD: Duration = Duration::from_secs(1);
+// share of the level kept each frame while paused
+const VU_PAUSED_DECAY: f32 = 0.6;
+
+/// Holds back a changed value until it has stayed put for `delay`, so bursts
+/// of corrections at track boundaries settle into a single change.
+struct Debounce<T> {
+    settled: T,
+    pending: Option<(T, Instant)>,
+    delay: Duration,
+}
+
+impl<T: Clone + PartialEq> Debounce<T> {
+    fn new(initial: T, delay: Duration) -> Self {
+        Self {
+            settled: initial,
+            pending: None,
+            delay,
+        }
+    }
+
+    fn update(&mut self, value: &T) -> &T {
+        if *value == self.settled {
+            self.pending = None;
+            return &self.settled;
+        }
+
+        match &self.pending {
+            Some((pending, since)) if pending == value => {
+                if since.elapsed() >= self.delay {
+                    self.settled = value.clone();
+                    self.pending = None;
+                }
+            }
+            _ => self.pending = Some((value.clone(), Instant::now())),
+        }
+
+        &self.settled
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+/// Which fields differed on the most recent metadata update.
+#[derive(Clone, Copy, Debug, Default)]
+struct ChangedFields {
+    artist: bool,
+    title: bool,
+    art: bool,
+}
+
+impl ChangedFields {
+    fn between(old: &NowPlaying, new: &NowPlaying) -> Self {
+        Self {
+            artist: old.artist != new.artist,
+            title: old.title != new.title,
+            art: old.art_url != new.art_url,
+        }
+    }
+}
+
+/// One channel of the VU meter: the level shown and the sample peak held
+/// above it.
+#[derive(Clone, Copy)]
+struct VuBar {
+    level: f32,
+    peak: f32,
+    peak_at: Instant,
+}
+
+impl VuBar {
+    fn new() -> Self {
+        Self {
+            level: 0.0,
+            peak: 0.0,
+            peak_at: Instant::now(),
+        }
+    }
+
+    /// Shows `level` and `peak`, or lets both fall towards zero while paused.
+    fn update(&mut self, level: f32, peak: f32, paused: bool) {
+        let (level, peak) = if paused {
+            (self.level * VU_PAUSED_DECAY, self.peak * VU_PAUSED_DECAY)
+        } else {
+            (level, peak)
+        };
+        self.level = level;
+        if peak >= self.peak || self.peak_at.elapsed() > VU_PEAK_HOLD {
+            self.peak = peak;
+            self.peak_at = Instant::now();
+        }
+    }
+}
+
+/// The sleep timer's duration picker: a preset, or minutes typed in.
+#[derive(Default)]
+struct SleepPicker {
+    selected: usize,
+    typed: String,
+}
+
+impl SleepPicker {
+    fn minutes(&self) -> Option<u64> {
+        if self.typed.is_empty() {
+            return Some(SLEEP_PRESETS_MIN[self.selected]);
+        }
+        self.typed.parse().ok().filter(|&m| m > 0)
+    }
+}
+
+/// An armed sleep timer. `task` sends the share of the volume to keep,
+/// stepping down to zero once the time is up.
+struct SleepTimer {
+    ends: Instant,
+    task: tokio::task::JoinHandle<()>,
+    // the volume when the fade started
+    faded_from: Option<f32>,
+}
+
+/// Whether there's anything to show yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AppState {
+    /// Waiting on the first audio and the first track; the splash is up.
+    Connecting,
+    Ready,
+}
 
 pub struct UIState {
+    app: AppState,
     wave_phase: f32,
+    // when the waveform was last moved along
+    wave_at: Instant,
+    fft: Arc<dyn Fft<f32>>,
     last_volume_change: Instant,
-    saved_volume: Option<f32>,
+    history: History,
+    show_history: bool,
+    title_first: bool,
+    show_details: bool,
+    show_help: bool,
+    layout: LayoutMode,
+    visualizer: Visualizer,
+    compact_controls: bool,
+    settled: Debounce<NowPlaying>,
+    /// Covers by URL, kept for flipping back through history. `None` if the
+    /// download or decoding failed.
+    art_cache: HashMap<String, Option<DynamicImage>>,
+    art_pending: HashSet<String>,
+    /// The last cover drawn, with the URL and size it was drawn for.
+    art_rendered: Option<(String, (u16, u16), Vec<Line<'static>>)>,
+    /// Shown while there's no cover. Made once so the filler doesn't
+    /// change from one frame (or track) to the next.
+    logo: Vec<Line<'static>>,
+    theme: Theme,
+    color_depth: ColorDepth,
+    /// Index into history (0 = most recent) while browsing, `None` when live.
+    browse: Option<usize>,
+    last_np: NowPlaying,
+    changed: ChangedFields,
+    changed_at: Instant,
+    /// Last successful metadata fetch, whether or not anything changed.
+    fetched_at: Option<Instant>,
+    track_changed_at: Option<Instant>,
+    /// When the borders started flashing for a new track.
+    flash_at: Option<Instant>,
+    /// When the audio last clipped.
+    clipped_at: Option<Instant>,
+    /// A one-off message under the status, and when it was posted.
+    notice: Option<(String, Instant)>,
+    sleep_picker: Option<SleepPicker>,
+    /// The band picked in the open equalizer.
+    eq_band: Option<usize>,
+    sleep: Option<SleepTimer>,
+    /// Index into the station list.
+    station: usize,
+    vu: [VuBar; 2],
 }
 
 impl UIState {
-    fn new() -> Self {
-        Self {
+    fn new(config: &Config) -> Result<Self> {
+        let color_depth = ColorDepth::new(config.color);
+        let theme = Theme::from_config(&config.theme).adapted(color_depth);
+        let logo_file = config
+            .logo_file
+            .as_deref()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read logo_file {}", path.display()))
+            })
+            .transpose()?;
+        Ok(Self {
+            app: AppState::Connecting,
             wave_phase: 0.0,
+            wave_at: Instant::now(),
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
             last_volume_change: Instant::now(),
-            saved_volume: None,
-        }
+            history: History::new(Duration::from_secs(config.min_listen_secs)),
+            show_history: false,
+            title_first: config.title_first,
+            show_details: false,
+            show_help: false,
+            station: 0,
+            vu: [VuBar::new(); 2],
+            layout: config.layout,
+            visualizer: config.visualizer,
+            compact_controls: config.compact_controls,
+            settled: Debounce::new(
+                NowPlaying::default(),
+                Duration::from_millis(config.track_settle_ms),
+            ),
+            art_cache: HashMap::new(),
+            art_pending: HashSet::new(),
+            art_rendered: None,
+            logo: logo_lines(&theme, color_depth, logo_file.as_deref()),
+            browse: None,
+            last_np: NowPlaying::default(),
+            changed: ChangedFields::default(),
+            changed_at: Instant::now() - Duration::from_secs(3600),
+            fetched_at: None,
+            track_changed_at: None,
+            flash_at: None,
+            clipped_at: None,
+            notice: None,
+            sleep_picker: None,
+            eq_band: None,
+            sleep: None,
+            theme,
+            color_depth,
+        })
+    }
+
+    fn flashing(&self) -> bool {
+        self.flash_at.is_some_and(|at| at.elapsed() < TRACK_FLASH)
+    }
+
+    fn clipping(&self) -> bool {
+        self.clipped_at.is_some_and(|at| at.elapsed() < CLIP_HOLD)
     }
 }
 
 pub async fn run_ui(
-    rx: Arc<tokio::sync::Mutex<watch::Receiver<NowPlaying>>>,
-    _client: Client,
-    control: PlayerControl,
-    _sink_info: crate::player::SinkInfo,
+    metadata: Subscription,
+    mut remote: Remote,
+    client: Client,
+    player: Option<(PlayerControl, SinkInfo)>,
+    config: Config,
+    shutdown: impl Future<Output = ()>,
 ) -> Result<()> {
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    crossterm::execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
+    let keymap = Keymap::new(&config.keybindings)?;
+    let started = Instant::now();
+    let Subscription {
+        now_playing: mut rx,
+        fetched,
+        demand,
+        source,
+    } = metadata;
+
+    // Without audio there's nothing to control; only the metadata is shown.
+    let control = player.as_ref().map(|(control, _)| control);
+    let audio = control.is_some();
+
+    // Plaza first, then whatever the config adds.
+    let mut stations = vec![(
+        Station {
+            name: "Plaza Radio".to_string(),
+            url: control.map(|c| c.stream_url()).unwrap_or_default(),
+            metadata_url: None,
+        },
+        MetadataSource::Plaza,
+    )];
+    stations.extend(config.stations.iter().map(|station| {
+        let source = match &station.metadata_url {
+            Some(url) => MetadataSource::Url(url.clone()),
+            None => MetadataSource::StreamOnly,
+        };
+        (station.clone(), source)
+    }));
+    let (switch_tx, mut switch_rx) = mpsc::unbounded_channel::<(usize, String)>();
+    let (sleep_tx, mut sleep_rx) = mpsc::unbounded_channel::<f32>();
+
+    let terminal_guard = TerminalGuard::enter()?;
+    let backend = CrosstermBackend::new(stdout());
     let mut terminal = Terminal::new(backend)?;
 
     let mut last_art_url: Option<String> = None;
-    let mut art_render: Option<String> = None;
+    let (art_tx, mut art_rx) = mpsc::unbounded_channel();
     let mut last_fetch = Instant::now() - Duration::from_secs(3600);
-    let mut ui_state = UIState::new();
+    let mut ui_state = UIState::new(&config)?;
+    // Metadata is only polled while the layout shows more than the stream's
+    // own titles give (or something else needs it); see the top of the loop.
+    let mut watching = None;
+    // The exported history should have every track, whatever's shown.
+    let _exporting = config.history_export.as_ref().map(|_| demand.watch());
+
+    let mut events = EventStream::new();
+    let mut redraw = true;
+    let mut quitting = false;
+    tokio::pin!(shutdown);
 
     loop {
-        let np = { rx.lock().await.borrow().clone() };
+        // The compact layout only shows the track, which the stream sends
+        // along with the audio; the API is for artwork and the details.
+        // Without audio there are no stream titles, so it's the API or nothing.
+        let wants_metadata = ui_state.layout != LayoutMode::Compact || player.is_none();
+        if wants_metadata != watching.is_some() {
+            watching = wants_metadata.then(|| demand.watch());
+        }
+
+        let np = rx.borrow_and_update().clone();
+        ui_state.fetched_at = *fetched.borrow();
+
+        // The panel shows `np` straight away; history and artwork wait for it to settle.
+        if np != ui_state.last_np {
+            if !np.same_track(&ui_state.last_np) {
+                // the first track to come in isn't a change
+                let first = ui_state.track_changed_at.is_none();
+                ui_state.track_changed_at = Some(Instant::now());
+                if !first && (np.artist.is_some() || np.title.is_some()) {
+                    alert(config.track_alert, &mut ui_state);
+                }
+            }
+            ui_state.changed = ChangedFields::between(&ui_state.last_np, &np);
+            ui_state.changed_at = Instant::now();
+            ui_state.last_np = np.clone();
+        }
+
+        // Audio first, then something to say about it, or give up waiting on that.
+        if ui_state.app == AppState::Connecting
+            && control.is_none_or(|c| c.heard_audio())
+            && (ui_state.fetched_at.is_some()
+                || np.artist.is_some()
+                || np.title.is_some()
+                || started.elapsed() >= SPLASH_MAX)
+        {
+            ui_state.app = AppState::Ready;
+        }
+
+        let status = mpris::Status {
+            track: np.clone(),
+            paused: control.is_none_or(|c| c.is_paused()),
+            volume: control.map_or(0.0, |c| c.volume()),
+        };
+        remote.status.send_if_modified(|current| {
+            let changed = *current != status;
+            *current = status;
+            changed
+        });
+
+        // whatever the layout, so an old clip isn't what lights CLIP later
+        if let Some((control, sink_info)) = &player {
+            if sink_info.levels.take_loudest() * control.volume() >= CLIP_LEVEL {
+                ui_state.clipped_at = Some(Instant::now());
+            }
+            if control.pause_if_idle() {
+                ui_state.notice = Some((
+                    "Nothing but silence from the stream; paused".to_string(),
+                    Instant::now(),
+                ));
+            }
+        }
 
-        let url_opt = np.art_url.clone();
+        let settled = ui_state.settled.update(&np).clone();
+        ui_state.history.observe(&settled);
+
+        let url_opt = settled.art_url.clone();
         if url_opt != last_art_url && last_fetch.elapsed() > Duration::from_secs(2) {
-            art_render = Some(generate_ascii());
+            if let Some(url) = &url_opt {
+                if !ui_state.art_cache.contains_key(url) && ui_state.art_pending.insert(url.clone())
+                {
+                    let client = client.clone();
+                    let url = url.clone();
+                    let art_tx = art_tx.clone();
+                    tokio::spawn(async move {
+                        let image = art::fetch(&client, &url).await.ok();
+                        let _ = art_tx.send((url, image));
+                    });
+                }
+            }
 
             last_art_url = url_opt.clone();
             last_fetch = Instant::now();
         }
 
-        if art_render.is_none() {
-            art_render = Some(generate_ascii());
-        }
+        // While browsing history, show that entry's track and cached art instead of the live one.
+        let browsed = ui_state
+            .browse
+            .and_then(|i| ui_state.history.entries().iter().rev().nth(i))
+            .map(|entry| entry.track.clone());
+        let shown = browsed.as_ref().unwrap_or(&np);
 
-        terminal.draw(|f| {
-            let size = f.size();
+        // Fields that just changed flash for a moment (not while browsing old tracks).
+        let highlight = if browsed.is_none()
+            && ui_state.changed_at.elapsed() < Duration::from_millis(config.highlight_ms)
+        {
+            ui_state.changed
+        } else {
+            ChangedFields::default()
+        };
+        let shown_art_url = match &browsed {
+            Some(track) => track.art_url.clone(),
+            None => last_art_url.clone(),
+        };
 
-            let chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
-                .split(size);
-
-            let left = Paragraph::new(art_render.as_deref().unwrap_or("[loading artwork...]"))
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .border_type(BorderType::Rounded)
-                        .border_style(Style::default().fg(Color::Magenta)),
-                );
-            f.render_widget(left, chunks[0]);
+        draw_if(&mut terminal, redraw, |f| {
+            let size = f.size();
 
-            let paused = control.is_paused();
-            let current_volume = control.volume();
-            let status_icon = if paused { "⏸" } else { "▶" };
-            let status_text = if paused { "Paused" } else { "Playing" };
+            // A terminal being restored or resized can briefly report a zero
+            // size; draw nothing that frame rather than laying out empty rects.
+            if size.width == 0 || size.height == 0 {
+                return;
+            }
 
-            let wave_visual = generate_waveform(&mut ui_state.wave_phase, !paused, current_volume);
-            let volume_bar = generate_pretty_volume_bar(current_volume);
-            let volume_recently_changed =
-                ui_state.last_volume_change.elapsed() < Duration::from_secs(2);
+            if ui_state.app == AppState::Connecting {
+                let station = &stations[ui_state.station].0.name;
+                draw_splash(f, size, station, started.elapsed(), &ui_state.theme);
+                return;
+            }
 
-            let mut lines = vec![];
+            let stacked = size.width < STACK_BELOW_WIDTH && size.height >= STACKED_MIN_HEIGHT;
+            let art_only = ui_state.layout == LayoutMode::ArtOnly
+                || (!stacked && (size.width < ART_ONLY_BELOW.0 || size.height < ART_ONLY_BELOW.1));
 
-            lines.push(Line::from(vec![
-                Span::raw("Status: "),
-                Span::styled(
-                    format!("{} {}", status_icon, status_text),
-                    if paused {
-                        Style::default().fg(Color::Yellow)
+            if ui_state.layout == LayoutMode::Compact {
+                let lines = compact_lines(
+                    &ui_state,
+                    control,
+                    &track_caption(shown, ui_state.title_first),
+                    size,
+                    &keymap,
+                );
+                f.render_widget(Paragraph::new(lines), size);
+            } else if art_only {
+                let caption = format!(
+                    "{} {}",
+                    short_status(control, browsed.is_some()),
+                    track_caption(shown, ui_state.title_first)
+                );
+                let flashing = ui_state.flashing();
+                draw_art_only(
+                    f,
+                    size,
+                    |area| artwork(&mut ui_state, shown_art_url.as_deref(), area),
+                    caption,
+                    highlight.artist || highlight.title,
+                    flashing,
+                );
+            } else {
+                let chunks = Layout::default()
+                    .direction(if stacked {
+                        Direction::Vertical
                     } else {
-                        Style::default()
-                            .fg(Color::Green)
-                            .add_modifier(Modifier::BOLD)
-                    },
-                ),
-            ]));
+                        Direction::Horizontal
+                    })
+                    .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+                    .split(size);
+
+                let left_block = Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .border_style(highlighted(
+                        Style::default().fg(if highlight.art {
+                            Color::Yellow
+                        } else {
+                            ui_state.theme.art_border
+                        }),
+                        ui_state.flashing(),
+                    ));
+                let left = if ui_state.show_history {
+                    Paragraph::new(history_lines(&ui_state.history, ui_state.title_first))
+                        .block(left_block.title(" History "))
+                } else {
+                    let art = artwork(
+                        &mut ui_state,
+                        shown_art_url.as_deref(),
+                        left_block.inner(chunks[0]),
+                    );
+                    Paragraph::new(art).block(left_block)
+                };
+                f.render_widget(left, chunks[0]);
 
-            lines.push(Line::from(""));
+                let (artist, title) = shown.display_parts(ui_state.title_first);
 
-            lines.push(Line::from(vec![
-                Span::styled("Title:  ", Style::default().fg(Color::Cyan)),
-                Span::styled(
-                    np.title.as_deref().unwrap_or("Unknown Title"),
-                    Style::default()
-                        .fg(Color::White)
-                        .add_modifier(Modifier::BOLD),
-                ),
-            ]));
+                let theme = ui_state.theme;
+                // inside the border, and beside the slider if there is one
+                let slider = control.is_some() && config.volume_style == VolumeStyle::Vertical;
+                let panel_width =
+                    chunks[1].width.saturating_sub(if slider { 4 } else { 2 }) as usize;
+                let wave_bars = panel_width.clamp(WAVE_BARS_MIN, WAVE_BARS_MAX);
+                let mut lines = match control {
+                    Some(control) => status_lines(control, &theme),
+                    None => vec![Line::from(vec![
+                        Span::raw("Status: "),
+                        Span::styled("♪ Metadata only", Style::default().fg(theme.label)),
+                    ])],
+                };
+                if let Some(sleep) = &ui_state.sleep {
+                    let left = sleep.ends.saturating_duration_since(Instant::now());
+                    let text = if left.is_zero() {
+                        "⏾ Fading out…".to_string()
+                    } else {
+                        format!(
+                            "⏾ Sleep in {}",
+                            minutes_seconds(left + Duration::from_secs(1))
+                        )
+                    };
+                    lines.push(Line::from(Span::styled(
+                        text,
+                        Style::default().fg(Color::Magenta),
+                    )));
+                }
+                if let Some((notice, at)) = &ui_state.notice {
+                    if at.elapsed() < NOTICE_SHOWN {
+                        lines.push(Line::from(Span::styled(
+                            notice.clone(),
+                            Style::default().fg(Color::Yellow),
+                        )));
+                    }
+                }
 
-            lines.push(Line::from(""));
+                if let Some(i) = ui_state.browse {
+                    lines.push(Line::from(Span::styled(
+                        format!(
+                            "◀ History {}/{} — [ ] to browse, Esc for live",
+                            i + 1,
+                            ui_state.history.entries().len()
+                        ),
+                        Style::default().fg(Color::Yellow),
+                    )));
+                }
+
+                lines.push(Line::from(""));
 
-            lines.push(Line::from(vec![
-                if volume_recently_changed {
-                    Span::styled("🔊 ", Style::default().fg(Color::Yellow))
+                if shown.is_live() {
+                    let mut live = vec![Span::styled(
+                        "🎙 Live",
+                        Style::default()
+                            .fg(theme.status_playing)
+                            .add_modifier(Modifier::BOLD),
+                    )];
+                    if let Some(title) = title.or(artist) {
+                        live.push(Span::styled(
+                            format!("  {}", title),
+                            highlighted(Style::default().fg(theme.title), highlight.title),
+                        ));
+                    }
+                    lines.push(Line::from(live));
                 } else {
-                    Span::raw("")
-                },
-                Span::styled("Volume: ", Style::default().fg(Color::Magenta)),
-                Span::styled(
-                    format!("{:.0}%", current_volume * 100.0),
-                    Style::default()
-                        .fg(Color::White)
-                        .add_modifier(Modifier::BOLD),
-                ),
-            ]));
+                    lines.push(Line::from(vec![
+                        Span::styled("Title:  ", Style::default().fg(theme.label)),
+                        Span::styled(
+                            title.unwrap_or("Unknown Title"),
+                            highlighted(
+                                Style::default()
+                                    .fg(theme.title)
+                                    .add_modifier(Modifier::BOLD),
+                                highlight.title,
+                            ),
+                        ),
+                    ]));
+                    lines.push(Line::from(vec![
+                        Span::styled("Artist: ", Style::default().fg(theme.label)),
+                        Span::styled(
+                            artist.unwrap_or("Unknown Artist"),
+                            highlighted(
+                                Style::default()
+                                    .fg(theme.title)
+                                    .add_modifier(Modifier::BOLD),
+                                highlight.artist,
+                            ),
+                        ),
+                    ]));
+                    for (label, value) in [("Album:  ", &shown.album), ("Genre:  ", &shown.genre)] {
+                        if let Some(value) = value {
+                            lines.push(Line::from(vec![
+                                Span::styled(label, Style::default().fg(theme.label)),
+                                Span::styled(value.clone(), Style::default().fg(theme.title)),
+                            ]));
+                        }
+                    }
+                }
+                // Only the current track's position is known, and talk has none
+                // worth showing.
+                if let Some(progress) = np.progress.filter(|_| browsed.is_none() && !np.is_live()) {
+                    lines.push(Line::from(generate_progress_bar(
+                        progress.elapsed_now(),
+                        progress.duration,
+                    )));
+                }
+                let stream_info = player
+                    .as_ref()
+                    .and_then(|(_, sink_info)| sink_info.stream_info.lock().ok()?.clone());
+                if let Some(info) = stream_info {
+                    lines.push(Line::from(Span::styled(
+                        stream_info_text(&info),
+                        Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+                    )));
+                }
 
-            lines.push(Line::from(volume_bar));
-            lines.push(Line::from(""));
+                lines.push(Line::from(""));
 
-            lines.push(Line::from(Span::styled(
-                "♫ Waveform ♫",
-                Style::default()
-                    .fg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD),
-            )));
-            lines.push(Line::from(wave_visual));
-            lines.push(Line::from(""));
+                if let Some((control, sink_info)) = &player {
+                    let paused = control.is_paused();
+                    let current_volume = control.volume();
+                    let volume_bar = generate_pretty_volume_bar(current_volume, panel_width);
+                    let volume_recently_changed =
+                        ui_state.last_volume_change.elapsed() < Duration::from_secs(2);
 
-            lines.push(Line::from(Span::styled(
-                "─── Controls ───",
-                Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
-            )));
-            lines.push(Line::from(vec![
-                Span::styled(
-                    "  Space",
-                    Style::default()
-                        .fg(Color::Yellow)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(" : pause/resume"),
-            ]));
-            lines.push(Line::from(vec![
-                Span::styled(
-                    "    +/-",
-                    Style::default()
-                        .fg(Color::Yellow)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(" : volume up/down"),
-            ]));
-            lines.push(Line::from(vec![
-                Span::styled(
-                    "      m",
-                    Style::default()
-                        .fg(Color::Yellow)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(" : mute/unmute"),
-            ]));
-            lines.push(Line::from(vec![
-                Span::styled(
-                    "      q",
-                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(" : quit"),
-            ]));
+                    lines.push(Line::from(vec![
+                        if volume_recently_changed {
+                            Span::styled("🔊 ", Style::default().fg(Color::Yellow))
+                        } else {
+                            Span::raw("")
+                        },
+                        Span::styled("Volume: ", Style::default().fg(theme.volume_label)),
+                        Span::styled(
+                            format!("{:.0}%", current_volume * 100.0),
+                            Style::default()
+                                .fg(Color::White)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                        if control.is_muted() {
+                            Span::styled(" (muted)", Style::default().fg(Color::Yellow))
+                        } else if control.is_ducked() {
+                            Span::styled(" (ducked)", Style::default().fg(Color::Yellow))
+                        } else {
+                            Span::raw("")
+                        },
+                        if control.is_normalizing() {
+                            Span::styled(" (normalized)", Style::default().fg(Color::Cyan))
+                        } else {
+                            Span::raw("")
+                        },
+                        if control.is_mono() {
+                            Span::styled(" (mono)", Style::default().fg(Color::Cyan))
+                        } else {
+                            Span::raw("")
+                        },
+                        if ui_state.clipping() {
+                            Span::styled(
+                                " CLIP",
+                                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                            )
+                        } else {
+                            Span::raw("")
+                        },
+                    ]));
 
-            let right = Paragraph::new(lines).block(
-                Block::default()
+                    if config.volume_style == VolumeStyle::Horizontal {
+                        lines.push(Line::from(volume_bar));
+                    }
+                    lines.push(Line::from(""));
+
+                    if ui_state.visualizer == Visualizer::Waveform {
+                        let spectrum = if paused {
+                            None
+                        } else {
+                            spectrum_levels(
+                                &sink_info.recent,
+                                ui_state.fft.as_ref(),
+                                current_volume,
+                                wave_bars,
+                            )
+                        };
+                        let wave_visual = match spectrum {
+                            Some(levels) => levels.into_iter().map(bar_char).collect(),
+                            None => {
+                                let since =
+                                    std::mem::replace(&mut ui_state.wave_at, Instant::now())
+                                        .elapsed();
+                                generate_waveform(
+                                    &mut ui_state.wave_phase,
+                                    since,
+                                    !paused,
+                                    current_volume,
+                                    wave_bars,
+                                )
+                            }
+                        };
+                        lines.push(Line::from(Span::styled(
+                            "♫ Waveform ♫",
+                            Style::default()
+                                .fg(theme.label)
+                                .add_modifier(Modifier::BOLD),
+                        )));
+                        lines.push(Line::from(Span::styled(
+                            wave_visual,
+                            Style::default().fg(theme.waveform),
+                        )));
+                        lines.push(Line::from(""));
+                    }
+
+                    if ui_state.visualizer == Visualizer::Vu {
+                        let (left, right) = sink_info.levels.get();
+                        let (left_peak, right_peak) = sink_info.levels.peaks();
+                        ui_state.vu[0].update(
+                            left * current_volume,
+                            left_peak * current_volume,
+                            paused,
+                        );
+                        ui_state.vu[1].update(
+                            right * current_volume,
+                            right_peak * current_volume,
+                            paused,
+                        );
+                        lines.push(Line::from(Span::styled(
+                            "♫ Levels ♫",
+                            Style::default()
+                                .fg(theme.label)
+                                .add_modifier(Modifier::BOLD),
+                        )));
+                        lines.push(vu_line("L", &ui_state.vu[0]));
+                        lines.push(vu_line("R", &ui_state.vu[1]));
+                        lines.push(Line::from(""));
+                    }
+                }
+
+                let right_block = Block::default()
                     .borders(Borders::ALL)
                     .border_type(BorderType::Rounded)
-                    .title(" ☆ Now Playing - Plaza Radio ☆ ")
+                    .title(format!(
+                        " ☆ Now Playing - {} ☆ ",
+                        stations[ui_state.station].0.name
+                    ))
                     .title_alignment(Alignment::Center)
-                    .border_style(Style::default().fg(Color::Cyan)),
-            );
-            f.render_widget(right, chunks[1]);
+                    .border_style(highlighted(
+                        Style::default().fg(theme.border),
+                        ui_state.flashing(),
+                    ));
+                let right_block = match clock_text(config.clock, config.session_timer, started) {
+                    Some(clock) => right_block.title(
+                        Title::from(Span::styled(clock, Style::default().fg(theme.label)))
+                            .position(Position::Bottom)
+                            .alignment(Alignment::Right),
+                    ),
+                    None => right_block,
+                };
+                let inner = right_block.inner(chunks[1]);
+                f.render_widget(right_block, chunks[1]);
+
+                let info_area = if let Some(control) =
+                    control.filter(|_| config.volume_style == VolumeStyle::Vertical)
+                {
+                    let split = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Min(0), Constraint::Length(2)].as_ref())
+                        .split(inner);
+                    let slider = generate_vertical_volume_bar(control.volume(), split[1].height)
+                        .into_iter()
+                        .map(Line::from)
+                        .collect::<Vec<_>>();
+                    f.render_widget(Paragraph::new(slider), split[1]);
+                    split[0]
+                } else {
+                    inner
+                };
+
+                let controls = controls_lines(&keymap, audio);
+                if stacked {
+                    lines.push(controls_hint_line(&keymap));
+                } else if ui_state.compact_controls
+                    || lines.len() + controls.len() > info_area.height as usize
+                {
+                    lines.push(compact_controls_line(&keymap, audio));
+                } else {
+                    lines.extend(controls);
+                }
+
+                f.render_widget(Paragraph::new(lines), info_area);
+            }
+
+            if ui_state.show_details {
+                let details = details_lines(control, &ui_state);
+                let area = centered_rect(60, details.len() as u16 + 2, size);
+                f.render_widget(Clear, area);
+                f.render_widget(
+                    Paragraph::new(details).block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded)
+                            .title(" Details ")
+                            .title_alignment(Alignment::Center)
+                            .border_style(Style::default().fg(Color::Yellow)),
+                    ),
+                    area,
+                );
+            }
+
+            if let (Some(band), Some(control)) = (ui_state.eq_band, control) {
+                let lines = eq_lines(control.eq(), band, &config);
+                let area = centered_rect(50, lines.len() as u16 + 2, size);
+                f.render_widget(Clear, area);
+                f.render_widget(
+                    Paragraph::new(lines).block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded)
+                            .title(" Equalizer ")
+                            .title_alignment(Alignment::Center)
+                            .border_style(Style::default().fg(ui_state.theme.border)),
+                    ),
+                    area,
+                );
+            }
+
+            if let Some(picker) = &ui_state.sleep_picker {
+                let lines = sleep_picker_lines(picker);
+                let area = centered_rect(34, lines.len() as u16 + 2, size);
+                f.render_widget(Clear, area);
+                f.render_widget(
+                    Paragraph::new(lines).block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded)
+                            .title(" Sleep timer ")
+                            .title_alignment(Alignment::Center)
+                            .border_style(Style::default().fg(Color::Magenta)),
+                    ),
+                    area,
+                );
+            }
+
+            if ui_state.show_help {
+                f.render_widget(Clear, size);
+                f.render_widget(
+                    Paragraph::new(help_lines(&keymap, audio)).block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .border_type(BorderType::Rounded)
+                            .title(" Keys (? to close) ")
+                            .title_alignment(Alignment::Center)
+                            .border_style(Style::default().fg(ui_state.theme.border)),
+                    ),
+                    size,
+                );
+            }
         })?;
 
-        if crossterm::event::poll(Duration::from_millis(200))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => {
+        // Tick only as fast as what's on screen needs; otherwise sleep until
+        // something happens.
+        let animating = control.is_some_and(|control| {
+            (!control.is_paused() && ui_state.visualizer != Visualizer::Off)
+                || control.prebuffer_fill().is_some()
+                || control.connection_state() != ConnectionState::Connected
+        }) || ui_state.changed_at.elapsed()
+            < Duration::from_millis(config.highlight_ms)
+            // a title too long for the line scrolls
+            || (ui_state.layout == LayoutMode::Compact
+                && track_caption(shown, ui_state.title_first).chars().count()
+                    > compact_caption_room(control, terminal.size()?.width))
+            || ui_state.app == AppState::Connecting
+            || ui_state.last_volume_change.elapsed() < Duration::from_secs(2)
+            || ui_state.flashing()
+            || ui_state.clipping()
+            || ui_state.settled.is_pending();
+        let tick = if animating {
+            FRAME_INTERVAL
+        } else {
+            IDLE_INTERVAL
+        };
+
+        redraw = true;
+        let event = tokio::select! {
+            event = events.next() => match event {
+                Some(event) => Some(event?),
+                None => break,
+            },
+            Ok(()) = rx.changed() => None,
+            Some(command) = remote.commands.recv() => {
+                if let Some(control) = control {
+                    apply_remote(control, &mut ui_state, command);
+                }
+                None
+            }
+            Some((station, url)) = switch_rx.recv() => {
+                // only if nothing else was picked while this was being resolved
+                if let Some(control) = control.filter(|_| station == ui_state.station) {
+                    control.switch_stream(&url);
+                }
+                None
+            }
+            Some(keep) = sleep_rx.recv() => {
+                let Some(sleep) = &mut ui_state.sleep else {
+                    continue;
+                };
+                if let Some(control) = control {
+                    let from = *sleep.faded_from.get_or_insert_with(|| control.volume());
+                    control.set_volume(from * keep);
+                }
+                if keep <= 0.0 {
+                    if let Some(control) = control {
                         control.stop();
+                    }
+                    break;
+                }
+                None
+            }
+            Some((url, image)) = art_rx.recv() => {
+                ui_state.art_pending.remove(&url);
+                ui_state.art_cache.insert(url, image);
+                prune_art_cache(&mut ui_state, last_art_url.as_deref());
+                None
+            }
+            // the player is stopped as it's dropped, on the way out
+            () = &mut shutdown => break,
+            _ = tokio::time::sleep(tick) => None,
+        };
+
+        if let Some(event) = event {
+            // start the next frame from a blank screen so nothing from the
+            // old size is left behind
+            if let Event::Resize(..) = event {
+                terminal.clear()?;
+            }
+            // nothing on screen depends on the mouse
+            redraw = !matches!(event, Event::Mouse(_));
+
+            if let Event::Key(key) = event {
+                if ui_state.sleep_picker.is_some() {
+                    pick_sleep(&mut ui_state, &keymap, &key, &sleep_tx);
+                    continue;
+                }
+                if let Some(control) = control.filter(|_| ui_state.eq_band.is_some()) {
+                    adjust_eq(&mut ui_state, control, &config, &keymap, &key);
+                    continue;
+                }
+                match keymap.action_for(&key) {
+                    Some(Action::Quit) => {
+                        quitting = true;
                         break;
                     }
-                    KeyCode::Char(' ') => {
-                        if control.is_paused() {
-                            control.play();
-                        } else {
-                            control.pause();
+                    Some(action) if action.needs_audio() => {
+                        if let Some(control) = control {
+                            apply_playback(control, &mut ui_state, &config, action);
+                        }
+                    }
+                    Some(Action::BrowseOlder) => {
+                        let len = ui_state.history.entries().len();
+                        if len > 0 {
+                            ui_state.browse = Some(match ui_state.browse {
+                                Some(i) => (i + 1).min(len - 1),
+                                None => 0,
+                            });
                         }
                     }
-                    KeyCode::Char('+') | KeyCode::Char('=') => {
-                        let new_vol = (control.volume() + 0.1).min(2.0);
-                        control.set_volume(new_vol);
-                        ui_state.last_volume_change = Instant::now();
+                    Some(Action::BrowseNewer) => {
+                        ui_state.browse = match ui_state.browse {
+                            Some(0) | None => None,
+                            Some(i) => Some(i - 1),
+                        };
                     }
-                    KeyCode::Char('-') => {
-                        let new_vol = (control.volume() - 0.1).max(0.0);
-                        control.set_volume(new_vol);
-                        ui_state.last_volume_change = Instant::now();
+                    Some(Action::BackToLive) => {
+                        ui_state.browse = None;
+                        ui_state.show_help = false;
                     }
-                    KeyCode::Char('m') => {
-                        let current_volume = control.volume();
-                        if current_volume > 0.0 {
-                            ui_state.saved_volume = Some(current_volume);
-                            control.set_volume(0.0);
-                        } else {
-                            let restore_volume = ui_state.saved_volume.unwrap_or(0.5);
-                            control.set_volume(restore_volume);
-                        }
-                        ui_state.last_volume_change = Instant::now();
+                    Some(Action::ToggleArtOnly) => {
+                        ui_state.layout = match ui_state.layout {
+                            LayoutMode::ArtOnly => LayoutMode::Split,
+                            _ => LayoutMode::ArtOnly,
+                        };
+                    }
+                    Some(Action::ToggleCompactView) => {
+                        ui_state.layout = match ui_state.layout {
+                            LayoutMode::Compact => LayoutMode::Split,
+                            _ => LayoutMode::Compact,
+                        };
                     }
-                    KeyCode::Up => {
-                        let new_vol = (control.volume() + 0.05).min(2.0);
-                        control.set_volume(new_vol);
-                        ui_state.last_volume_change = Instant::now();
+                    Some(Action::ToggleCompactControls) => {
+                        ui_state.compact_controls = !ui_state.compact_controls;
                     }
-                    KeyCode::Down => {
-                        let new_vol = (control.volume() - 0.05).max(0.0);
-                        control.set_volume(new_vol);
-                        ui_state.last_volume_change = Instant::now();
+                    Some(Action::ToggleDetails) => {
+                        ui_state.show_details = !ui_state.show_details;
+                    }
+                    Some(Action::SwapArtistTitle) => {
+                        ui_state.title_first = !ui_state.title_first;
+                    }
+                    Some(Action::ToggleHistory) => {
+                        ui_state.show_history = !ui_state.show_history;
+                    }
+                    Some(Action::Like) => like(&mut ui_state),
+                    Some(Action::OpenSearch) => open_search(&mut ui_state, &config),
+                    Some(Action::NextStation) => {
+                        switch_station(&mut ui_state, &stations, 1, &client, &source, &switch_tx);
+                    }
+                    Some(Action::PrevStation) => {
+                        switch_station(&mut ui_state, &stations, -1, &client, &source, &switch_tx);
+                    }
+                    Some(Action::SleepTimer) => match ui_state.sleep.take() {
+                        Some(sleep) => {
+                            sleep.task.abort();
+                            if let (Some(control), Some(volume)) = (control, sleep.faded_from) {
+                                control.set_volume(volume);
+                            }
+                            ui_state.notice = Some(("Sleep timer off".to_string(), Instant::now()));
+                        }
+                        None => ui_state.sleep_picker = Some(SleepPicker::default()),
+                    },
+                    Some(Action::ToggleHelp) => {
+                        ui_state.show_help = !ui_state.show_help;
                     }
-                    _ => {}
+                    Some(_) | None => {}
                 }
             }
         }
     }
 
-    disable_raw_mode()?;
-    crossterm::execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    if let Some(control) = control {
+        // before the fade out, or the silence at the end of it would be kept
+        save_state(control, &ui_state);
+        if quitting {
+            stop_with_fade(control, &config).await;
+        }
+    }
+    drop(terminal_guard);
+
+    if let Some(path) = &config.history_export {
+        ui_state.history.finish();
+        if let Err(e) = history::export(&ui_state.history, path, config.history_format) {
+            eprintln!("⚠️  Could not export history: {:#}", e);
+        }
+    }
     Ok(())
 }
 
-fn generate_ascii() -> String {
-    let mut art = String::new();
-
+/// The logo's rows, with the blanks filled in; `logo_lines` colors them.
+fn generate_ascii() -> Vec<String> {
     let lines = [
         "                                                 ",
         "                                                 ",
@@ -306,31 +1177,1028 @@ fn generate_ascii() -> String {
             .collect();
         processed_lines.push(new_line);
     }
+    processed_lines
+}
 
-    let start_color = (255, 140, 0); // orange
-    let end_color = (128, 0, 128); // urple
-    let n = processed_lines.len() as f32;
+/// Rings the bell and/or starts the borders flashing, as `alert` says, for
+/// a new track.
+fn alert(alert: TrackAlert, ui_state: &mut UIState) {
+    if alert.bell() {
+        let mut out = stdout();
+        let _ = out.write_all(b"\x07").and_then(|_| out.flush());
+    }
+    if alert.flash() {
+        ui_state.flash_at = Some(Instant::now());
+    }
+}
 
-    for (i, line) in processed_lines.iter().enumerate() {
-        let t = i as f32 / (n - 1.0);
-        let r = (start_color.0 as f32 * (1.0 - t) + end_color.0 as f32 * t) as u8;
-        let g = (start_color.1 as f32 * (1.0 - t) + end_color.1 as f32 * t) as u8;
-        let b = (start_color.2 as f32 * (1.0 - t) + end_color.2 as f32 * t) as u8;
-        art.push_str(&format!("\x1b[38;2;{};{};{}m{}\n", r, g, b, line));
+/// `style`, or an eye-catching variant of it for a field that just changed.
+fn highlighted(style: Style, changed: bool) -> Style {
+    if changed {
+        style.fg(Color::Black).bg(Color::Yellow)
+    } else {
+        style
     }
+}
+
+/// Raw mode and the alternate screen for as long as it's alive. The terminal
+/// is put back when it's dropped, however `run_ui` ends, and before a panic
+/// message is printed.
+struct TerminalGuard;
 
-    art.push_str("\x1b[0m");
-    art
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            previous(info);
+        }));
+
+        enable_raw_mode()?;
+        // from here on, dropping it undoes whatever got done
+        let guard = TerminalGuard;
+        crossterm::execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Undoes `TerminalGuard::enter`, carrying on past anything that fails.
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = crossterm::execute!(
+        stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        crossterm::cursor::Show
+    );
+}
+
+/// Fades the audio out over `quit_fade_ms` (capped so quitting stays snappy),
+/// then stops the player.
+async fn stop_with_fade(control: &PlayerControl, config: &Config) {
+    const MAX_FADE: Duration = Duration::from_secs(5);
+    let fade = Duration::from_millis(config.quit_fade_ms).min(MAX_FADE);
+
+    if !fade.is_zero() && !control.is_paused() {
+        control.ramp_volume(0.0, fade);
+        // a little extra so the last ramp step reaches the device before the cut
+        tokio::time::sleep(fade + Duration::from_millis(50)).await;
+    }
+
+    control.stop();
+}
+
+/// Runs `ui` as the next frame, unless nothing has changed since the last one.
+fn draw_if(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    redraw: bool,
+    ui: impl FnOnce(&mut Frame),
+) -> io::Result<()> {
+    if redraw {
+        terminal.draw(ui)?;
+    }
+    Ok(())
+}
+
+/// Carries out one of the actions that need audio (see `Action::needs_audio`).
+fn apply_playback(
+    control: &PlayerControl,
+    ui_state: &mut UIState,
+    config: &Config,
+    action: Action,
+) {
+    let volume = control.volume();
+    match action {
+        Action::Retry => control.retry(),
+        Action::TogglePause if control.is_paused() => control.play(),
+        Action::TogglePause => control.pause(),
+        Action::VolumeUp => control.set_volume(volume + 0.1),
+        Action::VolumeDown => control.set_volume(volume - 0.1),
+        Action::VolumeUpFine => control.set_volume(volume + 0.05),
+        Action::VolumeDownFine => control.set_volume(volume - 0.05),
+        Action::Mute => control.toggle_mute(),
+        Action::ToggleNormalize => control.toggle_normalize(),
+        Action::ToggleMono => control.toggle_mono(),
+        Action::Duck => control.toggle_duck(
+            config.duck_level,
+            Duration::from_millis(config.duck_ramp_ms),
+        ),
+        Action::ToggleRecording => toggle_recording(control, ui_state, config),
+        Action::ToggleEq => ui_state.eq_band = Some(0),
+        Action::FlushToLive => {
+            control.flush_to_live();
+            ui_state.notice = Some(("↻ live".to_string(), Instant::now()));
+        }
+        _ => return,
+    }
+    if !matches!(
+        action,
+        Action::Retry
+            | Action::TogglePause
+            | Action::ToggleMono
+            | Action::ToggleEq
+            | Action::FlushToLive
+            | Action::ToggleRecording
+    ) {
+        ui_state.last_volume_change = Instant::now();
+    }
+}
+
+/// Saves the recording that's going, or starts one named after the track.
+fn toggle_recording(control: &PlayerControl, ui_state: &mut UIState, config: &Config) {
+    let notice = if control.recording().is_some() {
+        match control.stop_recording() {
+            Ok(Some(path)) => format!("Saved {}", path.display()),
+            Ok(None) => return,
+            Err(e) => format!("Recording failed: {:#}", e),
+        }
+    } else {
+        let dir = config
+            .record_dir
+            .clone()
+            .or_else(dirs::audio_dir)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let path = recorder::recording_path(&dir, &ui_state.last_np);
+        let started = std::fs::create_dir_all(&dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| control.start_recording(&path));
+        match started {
+            Ok(()) => format!("Recording to {}", path.display()),
+            Err(e) => format!("Could not record: {:#}", e),
+        }
+    };
+    ui_state.notice = Some((notice, Instant::now()));
+}
+
+/// Adds the live track to the favorites file.
+fn like(ui_state: &mut UIState) {
+    let np = &ui_state.last_np;
+    if np.artist.is_none() && np.title.is_none() {
+        return;
+    }
+    let notice = match favorites::path().map(|path| favorites::add(&path, np)) {
+        Some(Ok(true)) => "★ liked".to_string(),
+        Some(Ok(false)) => "★ already liked".to_string(),
+        Some(Err(e)) => format!("Could not save favorite: {:#}", e),
+        None => "No config directory to keep favorites in".to_string(),
+    };
+    ui_state.notice = Some((notice, Instant::now()));
+}
+
+/// Keeps the volume and mute for next time, leaving out any fade in progress.
+fn save_state(control: &PlayerControl, ui_state: &UIState) {
+    let Some(path) = state::path() else {
+        return;
+    };
+    let volume = match ui_state.sleep.as_ref().and_then(|sleep| sleep.faded_from) {
+        Some(volume) => volume,
+        None => control.resting_volume(),
+    };
+    let state = state::State {
+        volume,
+        muted: control.is_muted(),
+    };
+    if let Err(e) = state::save(&path, &state) {
+        tracing::warn!("{:#}", e);
+    }
+}
+
+fn open_search(ui_state: &mut UIState, config: &Config) {
+    let Some(url) = search::url(&config.search_url, &ui_state.last_np) else {
+        return;
+    };
+    let notice = match search::open(&url) {
+        Ok(()) => "Opening the browser…".to_string(),
+        Err(e) => format!("Could not open a browser: {:#}", e),
+    };
+    ui_state.notice = Some((notice, Instant::now()));
+}
+
+/// Handles a key while the sleep timer's picker is open.
+fn pick_sleep(
+    ui_state: &mut UIState,
+    keymap: &Keymap,
+    key: &KeyEvent,
+    sleep_tx: &mpsc::UnboundedSender<f32>,
+) {
+    if key.kind != KeyEventKind::Press {
+        return;
+    }
+    if keymap.action_for(key) == Some(Action::SleepTimer) {
+        ui_state.sleep_picker = None;
+        return;
+    }
+    let Some(picker) = &mut ui_state.sleep_picker else {
+        return;
+    };
+    match key.code {
+        KeyCode::Up => picker.selected = picker.selected.saturating_sub(1),
+        KeyCode::Down => picker.selected = (picker.selected + 1).min(SLEEP_PRESETS_MIN.len() - 1),
+        KeyCode::Char(c) if c.is_ascii_digit() && picker.typed.len() < 4 => picker.typed.push(c),
+        KeyCode::Backspace => {
+            picker.typed.pop();
+        }
+        KeyCode::Enter => {
+            if let Some(minutes) = picker.minutes() {
+                let after = Duration::from_secs(minutes * 60);
+                ui_state.sleep = Some(SleepTimer {
+                    ends: Instant::now() + after,
+                    task: tokio::spawn(sleep_countdown(after, sleep_tx.clone())),
+                    faded_from: None,
+                });
+                ui_state.sleep_picker = None;
+            }
+        }
+        KeyCode::Esc => ui_state.sleep_picker = None,
+        _ => {}
+    }
+}
+
+/// Handles a key while the equalizer is open.
+fn adjust_eq(
+    ui_state: &mut UIState,
+    control: &PlayerControl,
+    config: &Config,
+    keymap: &Keymap,
+    key: &KeyEvent,
+) {
+    if key.kind != KeyEventKind::Press {
+        return;
+    }
+    let Some(band) = ui_state.eq_band else {
+        return;
+    };
+    if keymap.action_for(key) == Some(Action::ToggleEq) {
+        ui_state.eq_band = None;
+        return;
+    }
+    let gain = control.eq()[band];
+    match key.code {
+        KeyCode::Up => ui_state.eq_band = Some(band.saturating_sub(1)),
+        KeyCode::Down => ui_state.eq_band = Some((band + 1).min(eq::BANDS.len() - 1)),
+        KeyCode::Left => control.set_eq_band(band, gain - 1.0),
+        KeyCode::Right => control.set_eq_band(band, gain + 1.0),
+        KeyCode::Char('0') => control.set_eq_band(band, 0.0),
+        KeyCode::Char('p') => {
+            let names = eq::preset_names(&config.eq.presets);
+            let current = eq_preset_name(control.eq(), config);
+            let next = match current.and_then(|c| names.iter().position(|n| *n == c)) {
+                Some(i) => &names[(i + 1) % names.len()],
+                None => &names[0],
+            };
+            if let Some(gains) = eq::preset(next, &config.eq.presets) {
+                control.set_eq(gains);
+            }
+        }
+        KeyCode::Esc | KeyCode::Enter => ui_state.eq_band = None,
+        _ => {}
+    }
+}
+
+/// The preset `gains` are, if they're one.
+fn eq_preset_name(gains: [f32; 5], config: &Config) -> Option<String> {
+    eq::preset_names(&config.eq.presets)
+        .into_iter()
+        .find(|name| eq::preset(name, &config.eq.presets) == Some(gains))
+}
+
+/// A slider per band, from -`eq::MAX_DB` on the left to +`eq::MAX_DB`.
+fn eq_lines(gains: [f32; 5], selected: usize, config: &Config) -> Vec<Line<'static>> {
+    let half = eq::MAX_DB as i32;
+    let mut lines: Vec<Line> = eq::BANDS
+        .iter()
+        .zip(gains)
+        .enumerate()
+        .map(|(band, (&freq, gain))| {
+            let label = if freq >= 1000.0 {
+                format!("{}k", freq / 1000.0)
+            } else {
+                format!("{}", freq)
+            };
+            let db = gain.round() as i32;
+            let slider: String = (-half..=half)
+                .map(|step| match step {
+                    0 => '│',
+                    _ if (step > 0 && step <= db) || (step < 0 && step >= db) => '█',
+                    _ => '─',
+                })
+                .collect();
+            let text = format!(
+                "{} {:>4} Hz {} {:+3} dB",
+                if band == selected { "▶" } else { " " },
+                label,
+                slider,
+                db
+            );
+            if band == selected {
+                Line::from(Span::styled(
+                    text,
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ))
+            } else {
+                Line::from(text)
+            }
+        })
+        .collect();
+    lines.push(Line::from(format!(
+        "  Preset: {}",
+        eq_preset_name(gains, config).unwrap_or_else(|| "custom".to_string())
+    )));
+    lines.push(Line::from(Span::styled(
+        "↑↓ band  ←→ gain  0 reset  p preset  Esc close",
+        Style::default().fg(Color::Gray),
+    )));
+    lines
+}
+
+/// Waits `after`, then steps the share of the volume to keep down to zero
+/// over `SLEEP_FADE`.
+async fn sleep_countdown(after: Duration, keep: mpsc::UnboundedSender<f32>) {
+    tokio::time::sleep(after).await;
+    let steps = (SLEEP_FADE.as_millis() / SLEEP_FADE_STEP.as_millis()).max(1) as u32;
+    for step in 1..=steps {
+        tokio::time::sleep(SLEEP_FADE_STEP).await;
+        if keep.send(1.0 - step as f32 / steps as f32).is_err() {
+            return;
+        }
+    }
+}
+
+/// Carries out a request from MPRIS.
+fn apply_remote(control: &PlayerControl, ui_state: &mut UIState, command: mpris::Command) {
+    match command {
+        mpris::Command::Play => control.play(),
+        // A live stream can't be resumed from where it stopped, so Stop just pauses.
+        mpris::Command::Pause | mpris::Command::Stop => control.pause(),
+        mpris::Command::PlayPause if control.is_paused() => control.play(),
+        mpris::Command::PlayPause => control.pause(),
+        mpris::Command::SetVolume(volume) => {
+            control.set_volume(volume);
+            ui_state.last_volume_change = Instant::now();
+        }
+        mpris::Command::ChangeVolume(step) => {
+            control.set_volume(control.volume() + step);
+            ui_state.last_volume_change = Instant::now();
+        }
+    }
+}
+
+/// Moves `step` stations along. Metadata follows straight away; the audio
+/// once the station's playlist, if it has one, is resolved.
+fn switch_station(
+    ui_state: &mut UIState,
+    stations: &[(Station, MetadataSource)],
+    step: isize,
+    client: &Client,
+    source: &watch::Sender<MetadataSource>,
+    switched: &mpsc::UnboundedSender<(usize, String)>,
+) {
+    if stations.len() < 2 {
+        return;
+    }
+    let index = (ui_state.station as isize + step).rem_euclid(stations.len() as isize) as usize;
+    ui_state.station = index;
+    ui_state.browse = None;
+
+    let (station, metadata) = &stations[index];
+    source.send_replace(metadata.clone());
+
+    let client = client.clone();
+    let url = station.url.clone();
+    let switched = switched.clone();
+    tokio::spawn(async move {
+        let resolved = playlist::resolve(&client, &url).await.unwrap_or(url);
+        let _ = switched.send((index, resolved));
+    });
 }
 
-fn generate_waveform(phase: &mut f32, is_playing: bool, volume: f32) -> String {
-    let bar_count = 40;
+/// The playback status, with a gauge while buffering.
+fn status_lines(control: &PlayerControl, theme: &Theme) -> Vec<Line<'static>> {
+    let paused = control.is_paused();
+    let connection = control.connection_state();
+    // Filling the pre-buffer, or running low on audio while playing.
+    let buffering = control.prebuffer_fill().or_else(|| {
+        control
+            .buffer_health()
+            .filter(|&health| !paused && health < LOW_BUFFER)
+    });
+    let (status_icon, status_text) = match connection {
+        ConnectionState::Failed => (
+            "✖",
+            match control.last_error() {
+                Some(error) => format!(
+                    "Offline: {} — press R to retry",
+                    shortened(&error, LAST_ERROR_SHOWN)
+                ),
+                None => "Offline — press R to retry or q to quit".to_string(),
+            },
+        ),
+        ConnectionState::Connecting => ("◌", "Connecting…".to_string()),
+        ConnectionState::Reconnecting { attempt } => {
+            ("↻", format!("Reconnecting (attempt {})…", attempt))
+        }
+        ConnectionState::Connected if buffering.is_some() => ("⏳", "Buffering…".to_string()),
+        ConnectionState::Connected if control.is_idle() => {
+            ("💤", "Stream idle — play to reconnect".to_string())
+        }
+        ConnectionState::Connected if paused => ("⏸", "Paused".to_string()),
+        ConnectionState::Connected => ("▶", "Playing".to_string()),
+    };
+
+    let mut lines = vec![Line::from(vec![
+        Span::raw("Status: "),
+        Span::styled(
+            format!("{} {}", status_icon, status_text),
+            if connection == ConnectionState::Failed {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            } else if connection != ConnectionState::Connected {
+                Style::default().fg(Color::Yellow)
+            } else if paused || buffering.is_some() {
+                Style::default().fg(theme.status_paused)
+            } else {
+                Style::default()
+                    .fg(theme.status_playing)
+                    .add_modifier(Modifier::BOLD)
+            },
+        ),
+        if control.recording().is_some() {
+            Span::styled(
+                "  ● REC",
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            )
+        } else {
+            Span::raw("")
+        },
+    ])];
+    if let Some(fill) = buffering {
+        lines.push(Line::from(generate_buffer_gauge(
+            fill,
+            control.buffered_duration(),
+        )));
+    }
+    lines
+}
+
+fn control_key_style(actions: &[Action]) -> Style {
+    let color = if actions == [Action::Quit] {
+        Color::Red
+    } else {
+        Color::Yellow
+    };
+    Style::default().fg(color).add_modifier(Modifier::BOLD)
+}
+
+/// The controls worth listing: all of them, or without audio, those that
+/// still do something.
+fn shown_controls(
+    audio: bool,
+) -> impl Iterator<Item = &'static (&'static [Action], &'static str, &'static str)> {
+    keymap::CONTROLS
+        .iter()
+        .filter(move |(actions, _, _)| audio || !actions.iter().all(|a| a.needs_audio()))
+}
+
+fn controls_lines(keymap: &Keymap, audio: bool) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(Span::styled(
+        "─── Controls ───",
+        Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+    ))];
+    for &(actions, description, _) in shown_controls(audio) {
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("{:>7}", keymap.label(actions)),
+                control_key_style(actions),
+            ),
+            Span::raw(format!(" : {}", description)),
+        ]));
+    }
+    lines
+}
+
+/// All the controls on one line, e.g. "Space:play +/-:vol m:mute".
+fn compact_controls_line(keymap: &Keymap, audio: bool) -> Line<'static> {
+    let mut spans = Vec::new();
+    for &(actions, _, short) in shown_controls(audio) {
+        if !spans.is_empty() {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(Span::styled(
+            keymap.label(actions),
+            control_key_style(actions),
+        ));
+        spans.push(Span::styled(
+            format!(":{}", short),
+            Style::default().fg(Color::Gray),
+        ));
+    }
+    Line::from(spans)
+}
+
+/// Just where to find the keys, for layouts too cramped for the list.
+fn controls_hint_line(keymap: &Keymap) -> Line<'static> {
+    let mut spans = Vec::new();
+    for (actions, what) in [
+        (&[Action::ToggleHelp][..], "keys"),
+        (&[Action::Quit], "quit"),
+    ] {
+        if !spans.is_empty() {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(Span::styled(
+            keymap.label(actions),
+            control_key_style(actions),
+        ));
+        spans.push(Span::styled(
+            format!(":{}", what),
+            Style::default().fg(Color::Gray),
+        ));
+    }
+    Line::from(spans)
+}
+
+fn sleep_picker_lines(picker: &SleepPicker) -> Vec<Line<'static>> {
+    let mut lines: Vec<Line> = SLEEP_PRESETS_MIN
+        .iter()
+        .enumerate()
+        .map(|(i, minutes)| {
+            if picker.typed.is_empty() && i == picker.selected {
+                Line::from(Span::styled(
+                    format!("▶ {} min", minutes),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ))
+            } else {
+                Line::from(format!("  {} min", minutes))
+            }
+        })
+        .collect();
+    lines.push(Line::from(format!("  or type minutes: {}_", picker.typed)));
+    lines.push(Line::from(Span::styled(
+        "Enter to start, Esc to cancel",
+        Style::default().fg(Color::Gray),
+    )));
+    lines
+}
+
+/// Every action with all of its keys.
+fn help_lines(keymap: &Keymap, audio: bool) -> Vec<Line<'static>> {
+    keymap
+        .bindings()
+        .iter()
+        .filter(|(action, _)| audio || !action.needs_audio())
+        .map(|(action, keys)| {
+            let keys = if keys.is_empty() {
+                "(unbound)".to_string()
+            } else {
+                keys.iter()
+                    .map(|&key| keymap::key_name(key))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            Line::from(vec![
+                Span::styled(format!("{:>12}", keys), control_key_style(&[*action])),
+                Span::raw(format!("  {}", action.description())),
+            ])
+        })
+        .collect()
+}
+
+/// What's playing as one line, e.g. "Artist — Title", or "🎙 Live — Title"
+/// while the station's talking.
+fn track_caption(track: &NowPlaying, title_first: bool) -> String {
+    let (artist, title) = track.display_parts(title_first);
+    if track.is_live() {
+        return match title.or(artist) {
+            Some(title) => format!("🎙 Live — {}", title),
+            None => "🎙 Live".to_string(),
+        };
+    }
+    match (artist, title) {
+        (Some(a), Some(t)) => format!("{} — {}", a, t),
+        (None, Some(t)) => t.to_string(),
+        (Some(a), None) => a.to_string(),
+        (None, None) => "Plaza Radio".to_string(),
+    }
+}
+
+/// A one-character status for layouts without room for the full one.
+fn short_status(control: Option<&PlayerControl>, browsing: bool) -> &'static str {
+    match control {
+        _ if browsing => "◀",
+        None => "♪",
+        Some(control) => match control.connection_state() {
+            ConnectionState::Failed => "✖",
+            ConnectionState::Connecting => "◌",
+            ConnectionState::Reconnecting { .. } => "↻",
+            ConnectionState::Connected if control.prebuffer_fill().is_some() => "⏳",
+            ConnectionState::Connected if control.is_paused() => "⏸",
+            ConnectionState::Connected => "▶",
+        },
+    }
+}
+
+/// The compact layout: the track on the first row, scrolling when it's too
+/// long, then a thin volume bar, then a notice or where the keys are, for as
+/// many rows as `area` has.
+fn compact_lines(
+    ui_state: &UIState,
+    control: Option<&PlayerControl>,
+    caption: &str,
+    area: Rect,
+    keymap: &Keymap,
+) -> Vec<Line<'static>> {
+    let theme = &ui_state.theme;
+    let icon = short_status(control, ui_state.browse.is_some());
+    let volume = compact_volume(control);
+    let room = compact_caption_room(control, area.width);
+
+    let mut lines = vec![Line::from(vec![
+        Span::styled(format!("{} ", icon), Style::default().fg(theme.label)),
+        Span::styled(
+            format!(
+                "{:<room$}",
+                scrolled(caption, room, ui_state.changed_at.elapsed()),
+                room = room
+            ),
+            Style::default()
+                .fg(theme.title)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(volume, Style::default().fg(theme.volume_label)),
+    ])];
+    if let Some(control) = control {
+        let filled = (control.volume().clamp(0.0, 1.0) * area.width as f32).round() as usize;
+        lines.push(Line::from(vec![
+            Span::styled(
+                "━".repeat(filled),
+                Style::default().fg(if control.is_muted() {
+                    Color::DarkGray
+                } else {
+                    theme.volume_label
+                }),
+            ),
+            Span::styled(
+                "─".repeat((area.width as usize).saturating_sub(filled)),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+    }
+    match &ui_state.notice {
+        Some((notice, at)) if at.elapsed() < NOTICE_SHOWN => {
+            lines.push(Line::from(Span::styled(
+                notice.clone(),
+                Style::default().fg(Color::Yellow),
+            )));
+        }
+        _ => lines.push(controls_hint_line(keymap)),
+    }
+    lines.truncate(area.height as usize);
+    lines
+}
+
+fn compact_volume(control: Option<&PlayerControl>) -> String {
+    control
+        .map(|control| format!(" {:.0}%", control.volume() * 100.0))
+        .unwrap_or_default()
+}
+
+/// How wide the caption in the compact layout can be before it scrolls:
+/// what's left of `width` after the icon and the volume.
+fn compact_caption_room(control: Option<&PlayerControl>, width: u16) -> usize {
+    (width as usize).saturating_sub(2 + compact_volume(control).chars().count())
+}
+
+/// `width` characters of `text`, wrapping around over time if it's longer.
+fn scrolled(text: &str, width: usize, since: Duration) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= width {
+        return text.to_string();
+    }
+    // a gap between the end and the start coming round again
+    let looped: Vec<char> = chars.iter().copied().chain("   ".chars()).collect();
+    let start = (since.as_millis() / SCROLL_STEP.as_millis()) as usize % looped.len();
+    looped.iter().cycle().skip(start).take(width).collect()
+}
+
+/// "Connecting to Plaza Radio…" in the middle of `area`, with a spinner
+/// that's been going for `since`.
+fn draw_splash(f: &mut Frame, area: Rect, station: &str, since: Duration, theme: &Theme) {
+    let step = (since.as_millis() / SPINNER_STEP.as_millis()) as usize;
+    let line = Line::from(vec![
+        Span::styled(
+            format!("{} ", SPINNER[step % SPINNER.len()]),
+            Style::default().fg(theme.label),
+        ),
+        Span::styled(
+            format!("Connecting to {}…", station),
+            Style::default()
+                .fg(theme.title)
+                .add_modifier(Modifier::BOLD),
+        ),
+    ]);
+    let row = Rect {
+        y: area.y + area.height / 2,
+        height: 1,
+        ..area
+    };
+    f.render_widget(Paragraph::new(line).alignment(Alignment::Center), row);
+}
+
+/// Artwork filling `area`, with a single caption line along the bottom.
+fn draw_art_only(
+    f: &mut Frame,
+    area: Rect,
+    art: impl FnOnce(Rect) -> Vec<Line<'static>>,
+    caption: String,
+    flash: bool,
+    flash_border: bool,
+) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(highlighted(
+            Style::default().fg(Color::Magenta),
+            flash_border,
+        ));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(inner);
+
+    f.render_widget(Paragraph::new(art(rows[0])), rows[0]);
+    f.render_widget(
+        Paragraph::new(Line::from(Span::styled(
+            caption,
+            highlighted(
+                Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+                flash,
+            ),
+        )))
+        .alignment(Alignment::Center),
+        rows[1],
+    );
+}
+
+/// The cover at `url` drawn to fit `area`, or the logo while there isn't one.
+/// Nothing at all if `area` is too small for either to make sense.
+fn artwork(ui_state: &mut UIState, url: Option<&str>, area: Rect) -> Vec<Line<'static>> {
+    if area.width < ART_HIDDEN_BELOW.0 || area.height < ART_HIDDEN_BELOW.1 {
+        return Vec::new();
+    }
+    let (Some(url), size) = (url, (area.width, area.height)) else {
+        return ui_state.logo.clone();
+    };
+    let Some(Some(image)) = ui_state.art_cache.get(url) else {
+        return ui_state.logo.clone();
+    };
+
+    if let Some((drawn_url, drawn_size, lines)) = &ui_state.art_rendered {
+        if drawn_url == url && *drawn_size == size {
+            return lines.clone();
+        }
+    }
+
+    let lines = art::render(image, area.width, area.height, ui_state.color_depth);
+    ui_state.art_rendered = Some((url.to_string(), size, lines.clone()));
+    lines
+}
+
+/// The logo, or `custom` in its place: cut down to `MAX_LOGO` cells, in its
+/// own colors if it has any and the art gradient if not.
+fn logo_lines(theme: &Theme, depth: ColorDepth, custom: Option<&str>) -> Vec<Line<'static>> {
+    let logo = match custom {
+        Some(text) => {
+            let (width, height) = MAX_LOGO;
+            if text.contains('\x1b') {
+                return art::ansi_lines(text, depth)
+                    .into_iter()
+                    .take(height)
+                    .map(|line| truncated(line, width))
+                    .collect();
+            }
+            text.lines()
+                .take(height)
+                .map(|line| line.chars().take(width).collect())
+                .collect()
+        }
+        None => generate_ascii(),
+    };
+    let rows = logo.len();
+    logo.into_iter()
+        .enumerate()
+        .map(|(row, line)| {
+            Line::styled(
+                line,
+                Style::default().fg(depth.adapt(theme.art_gradient(row, rows))),
+            )
+        })
+        .collect()
+}
+
+/// `text` cut down to `max` characters, with an ellipsis if anything went.
+fn shortened(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut kept: String = text.chars().take(max.saturating_sub(1)).collect();
+    kept.push('…');
+    kept
+}
+
+/// `line` with only its first `width` characters, styles kept.
+fn truncated(line: Line<'static>, width: usize) -> Line<'static> {
+    let mut left = width;
+    let spans = line
+        .spans
+        .into_iter()
+        .map_while(|span| {
+            let kept: String = span.content.chars().take(left).collect();
+            left -= kept.chars().count();
+            (!kept.is_empty()).then(|| Span::styled(kept, span.style))
+        })
+        .collect::<Vec<_>>();
+    Line::from(spans)
+}
+
+/// Keeps the art cache from growing past what history (and the live track)
+/// can still refer to.
+fn prune_art_cache(ui_state: &mut UIState, live: Option<&str>) {
+    const MAX_ENTRIES: usize = 64;
+    if ui_state.art_cache.len() <= MAX_ENTRIES {
+        return;
+    }
+
+    let wanted: Vec<&str> = ui_state
+        .history
+        .entries()
+        .iter()
+        .rev()
+        .take(MAX_ENTRIES)
+        .filter_map(|entry| entry.track.art_url.as_deref())
+        .chain(live)
+        .collect();
+    ui_state
+        .art_cache
+        .retain(|url, _| wanted.contains(&url.as_str()));
+}
+
+fn details_lines(control: Option<&PlayerControl>, ui_state: &UIState) -> Vec<Line<'static>> {
+    let label = Style::default().fg(ui_state.theme.label);
+    let ago = |at: Option<Instant>| match at {
+        Some(at) => format!("{}s ago", at.elapsed().as_secs()),
+        None => "never".to_string(),
+    };
+
+    let mut lines = match control {
+        Some(control) => player_details(control, &ui_state.theme),
+        None => vec![Line::from(vec![
+            Span::styled("Audio:      ", label),
+            Span::raw("off"),
+        ])],
+    };
+    lines.extend([
+        Line::from(vec![
+            Span::styled("Fetched:    ", label),
+            Span::raw(ago(ui_state.fetched_at)),
+        ]),
+        Line::from(vec![
+            Span::styled("New track:  ", label),
+            Span::raw(ago(ui_state.track_changed_at)),
+        ]),
+    ]);
+    lines
+}
+
+fn player_details(control: &PlayerControl, theme: &Theme) -> Vec<Line<'static>> {
+    let label = Style::default().fg(theme.label);
+    let connection = match control.connection_state() {
+        ConnectionState::Connected if control.is_idle() => "idle, disconnected".to_string(),
+        ConnectionState::Connecting => "connecting".to_string(),
+        ConnectionState::Connected => "connected".to_string(),
+        ConnectionState::Reconnecting { attempt } => format!("reconnecting (attempt {})", attempt),
+        ConnectionState::Failed => match control.last_error() {
+            Some(error) => format!("gave up: {}", error),
+            None => "gave up".to_string(),
+        },
+    };
+
+    vec![
+        Line::from(vec![
+            Span::styled("Stream:     ", label),
+            Span::raw(control.stream_url()),
+        ]),
+        Line::from(vec![
+            Span::styled("Backend:    ", label),
+            Span::raw(control.backend().name()),
+        ]),
+        Line::from(vec![
+            Span::styled("Connection: ", label),
+            Span::raw(connection),
+        ]),
+        Line::from(vec![
+            Span::styled("Buffered:   ", label),
+            Span::raw(
+                control
+                    .buffered_duration()
+                    .map(|time| format!("{} ms", time.as_millis()))
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("Underruns:  ", label),
+            Span::raw(format!(
+                "{} ({:.1}% of samples were silence)",
+                control.underruns(),
+                control.starved_ratio() * 100.0
+            )),
+        ]),
+        Line::from(vec![
+            Span::styled("Recording:  ", label),
+            Span::raw(control.recording().unwrap_or_else(|| "off".to_string())),
+        ]),
+    ]
+}
+
+/// A `width` x `height` rect in the middle of `area`, shrunk to fit if needed.
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn history_lines(history: &History, title_first: bool) -> Vec<Line<'static>> {
+    if history.entries().is_empty() {
+        return vec![Line::from(Span::styled(
+            "Nothing played yet",
+            Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+        ))];
+    }
+
+    history
+        .entries()
+        .iter()
+        .rev()
+        .map(|entry| {
+            let secs = entry.listened.as_secs();
+            let (artist, title) = entry.track.display_parts(title_first);
+            Line::from(vec![
+                Span::styled(
+                    artist.unwrap_or("Unknown Artist").to_string(),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw(" — "),
+                Span::styled(
+                    title.unwrap_or("Unknown Title").to_string(),
+                    Style::default().fg(Color::White),
+                ),
+                Span::styled(
+                    format!(" ({}:{:02})", secs / 60, secs % 60),
+                    Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+                ),
+            ])
+        })
+        .collect()
+}
+
+/// Stand-in animation for when there's no audio to analyse.
+/// Moves the wave along by `since`, the time since the last frame, so it
+/// looks the same whatever the frame rate, and spreads the same waves over
+/// however many bars there are.
+fn generate_waveform(
+    phase: &mut f32,
+    since: Duration,
+    is_playing: bool,
+    volume: f32,
+    bar_count: usize,
+) -> String {
     let mut rng = rand::thread_rng();
 
+    let since = since.as_secs_f32();
     if is_playing {
-        *phase += 0.2;
+        // wrapped so it keeps its precision over a long session; 20π is a
+        // whole number of turns for all three waves below
+        *phase = (*phase + WAVE_SPEED * since) % (std::f32::consts::TAU * 10.0);
     } else {
-        *phase *= 0.95;
+        *phase *= WAVE_DECAY.powf(since);
     }
 
     let mut bars = String::new();
@@ -352,30 +2220,125 @@ fn generate_waveform(phase: &mut f32, is_playing: bool, volume: f32) -> String {
             level
         };
 
-        let bar_char = match final_level {
-            0 => '▁',
-            1 => '▂',
-            2 => '▃',
-            3 => '▄',
-            4 => '▅',
-            5 => '▆',
-            6 => '▇',
-            _ => '█',
-        };
-
-        bars.push(bar_char);
+        bars.push(bar_char(final_level));
     }
 
     bars
 }
 
-fn generate_pretty_volume_bar(volume: f32) -> String {
-    let vol_percent = (volume * 100.0) as usize;
-    let bar_length = 20;
+fn bar_char(level: u8) -> char {
+    match level {
+        0 => '▁',
+        1 => '▂',
+        2 => '▃',
+        3 => '▄',
+        4 => '▅',
+        5 => '▆',
+        6 => '▇',
+        _ => '█',
+    }
+}
+
+/// `bars` levels (0-8) from an FFT of the last `FFT_SIZE` samples played, on
+/// a log frequency scale. `None` until enough audio has been played.
+fn spectrum_levels(
+    recent: &RecentSamples,
+    fft: &dyn Fft<f32>,
+    volume: f32,
+    bars: usize,
+) -> Option<Vec<u8>> {
+    let mut buf: Vec<Complex<f32>> = {
+        let recent = recent.lock().ok()?;
+        if recent.len() < FFT_SIZE {
+            return None;
+        }
+        recent
+            .iter()
+            .skip(recent.len() - FFT_SIZE)
+            .enumerate()
+            .map(|(i, &s)| {
+                // Hann window, so the edges of the slice don't smear across every bin
+                let w = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos();
+                Complex::new(s * w, 0.0)
+            })
+            .collect()
+    };
+    fft.process(&mut buf);
+
+    let bins = FFT_SIZE / 2;
+    let magnitudes: Vec<f32> = buf[..bins]
+        .iter()
+        .map(|c| c.norm() / bins as f32 * volume)
+        .collect();
+
+    let levels = (0..bars)
+        .map(|bar| {
+            // skip bin 0 (DC) and spread the rest logarithmically, at least
+            // one bin per bar so the low end doesn't repeat itself
+            let edge = |b: usize| (bins as f32).powf(b as f32 / bars as f32) as usize;
+            let lo = edge(bar).max(bar + 1);
+            let hi = edge(bar + 1).clamp(lo + 1, bins);
+            let peak = magnitudes[lo..hi].iter().fold(0.0f32, |a, &b| a.max(b));
+
+            // -60 dB and below is an empty bar, 0 dB a full one
+            let db = 20.0 * peak.max(1e-6).log10();
+            ((db + 60.0) / 60.0 * 8.0).clamp(0.0, 8.0) as u8
+        })
+        .collect();
+    Some(levels)
+}
+
+/// A horizontal VU bar in dBFS: green up to -12 dB, yellow to -3, red above,
+/// with the held peak marked.
+fn vu_line(label: &str, bar: &VuBar) -> Line<'static> {
+    let db = |level: f32| (20.0 * level.max(f32::MIN_POSITIVE).log10()).clamp(VU_FLOOR_DB, 0.0);
+    let cells =
+        |level: f32| ((db(level) - VU_FLOOR_DB) / -VU_FLOOR_DB * VU_WIDTH as f32).round() as usize;
+    let filled = cells(bar.level);
+    let peak = cells(bar.peak);
+
+    let mut spans = vec![Span::styled(
+        format!("{} ", label),
+        Style::default().fg(Color::Magenta),
+    )];
+    for i in 0..VU_WIDTH {
+        let zone = if i >= VU_WIDTH * 15 / 16 {
+            Color::Red
+        } else if i >= VU_WIDTH * 3 / 4 {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        let (cell, color) = if i < filled {
+            ('█', zone)
+        } else if i + 1 == peak {
+            ('│', zone)
+        } else {
+            ('·', Color::DarkGray)
+        };
+        spans.push(Span::styled(cell.to_string(), Style::default().fg(color)));
+    }
+
+    let readout = if filled == 0 {
+        "   -∞ dB".to_string()
+    } else {
+        format!(" {:>4.0} dB", db(bar.level))
+    };
+    spans.push(Span::styled(readout, Style::default().fg(Color::Gray)));
+    Line::from(spans)
+}
+
+/// Fills up to 100%; anything past that is amplification, shown as "+NN%".
+/// Sized to fit `width` columns, within limits.
+fn generate_pretty_volume_bar(volume: f32, width: usize) -> String {
+    let vol_percent = (volume * 100.0).round() as usize;
+    let bar_length = width
+        .saturating_sub(VOLUME_BAR_EXTRAS)
+        .clamp(VOLUME_BAR_MIN, VOLUME_BAR_MAX);
     let filled = (vol_percent * bar_length / 100).min(bar_length);
 
     let mut bar = String::new();
-    bar.push_str("│");
+    bar.push('│');
 
     for i in 0..bar_length {
         if i < filled {
@@ -393,7 +2356,11 @@ fn generate_pretty_volume_bar(volume: f32) -> String {
         }
     }
 
-    bar.push_str("│");
+    bar.push('│');
+
+    if vol_percent > 100 {
+        bar.push_str(&format!(" +{}%", vol_percent - 100));
+    }
 
     if volume == 0.0 {
         bar.push_str(" 🔇");
@@ -407,3 +2374,134 @@ fn generate_pretty_volume_bar(volume: f32) -> String {
 
     bar
 }
+
+/// E.g. "│▓▓▓▓········│ 1:23 / 4:05".
+fn generate_progress_bar(elapsed: Duration, duration: Duration) -> String {
+    let bar_length = 20;
+    let share = elapsed.as_secs_f32() / duration.as_secs_f32().max(1.0);
+    let filled = ((share * bar_length as f32) as usize).min(bar_length);
+
+    format!(
+        "│{}{}│ {} / {}",
+        "▓".repeat(filled),
+        "·".repeat(bar_length - filled),
+        minutes_seconds(elapsed),
+        minutes_seconds(duration)
+    )
+}
+
+/// E.g. "320kbps MP3 · 44.1kHz · stereo".
+fn stream_info_text(info: &StreamInfo) -> String {
+    let codec = info.codec.to_uppercase();
+    let mut parts = vec![match info.bitrate_kbps {
+        Some(kbps) => format!("{}kbps {}", kbps, codec),
+        None => codec,
+    }];
+    if let Some(rate) = info.sample_rate {
+        parts.push(format!("{}kHz", rate as f32 / 1000.0));
+    }
+    parts.extend(info.channels.clone());
+    parts.join(" · ")
+}
+
+/// " 21:07 " or " 9:07 PM ", plus how long since `started` with
+/// `session_timer`. None with the clock off. Without the local time only
+/// the session timer is left.
+fn clock_text(format: ClockFormat, session_timer: bool, started: Instant) -> Option<String> {
+    if format == ClockFormat::Off {
+        return None;
+    }
+    let time = local_hour_minute().map(|(hour, minute)| match format {
+        ClockFormat::Twelve => format!(
+            "{}:{:02} {}",
+            (hour + 11) % 12 + 1,
+            minute,
+            if hour < 12 { "AM" } else { "PM" }
+        ),
+        ClockFormat::TwentyFour | ClockFormat::Off => format!("{:02}:{:02}", hour, minute),
+    });
+    let minutes = started.elapsed().as_secs() / 60;
+    let up = format!("up {}h {:02}m", minutes / 60, minutes % 60);
+    match (time, session_timer) {
+        (Some(time), false) => Some(format!(" {} ", time)),
+        (Some(time), true) => Some(format!(" {} · {} ", time, up)),
+        (None, true) => Some(format!(" {} ", up)),
+        (None, false) => None,
+    }
+}
+
+/// The hour and minute in the local time zone, which std doesn't know about.
+/// None if the C library can't say.
+#[cfg(unix)]
+fn local_hour_minute() -> Option<(u32, u32)> {
+    // SAFETY: localtime_r only writes to the tm it's given, and returns null
+    // rather than leave it half filled in.
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        tm
+    };
+    Some((tm.tm_hour as u32, tm.tm_min as u32))
+}
+
+/// Elsewhere there's no time zone to go by, and UTC passed off as local
+/// time would be wrong, so there's no clock.
+#[cfg(not(unix))]
+fn local_hour_minute() -> Option<(u32, u32)> {
+    None
+}
+
+fn minutes_seconds(time: Duration) -> String {
+    let secs = time.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+fn generate_buffer_gauge(fill: f32, buffered: Option<Duration>) -> Vec<Span<'static>> {
+    let width = 20;
+    let filled = ((fill.clamp(0.0, 1.0) * width as f32).round() as usize).min(width);
+
+    vec![
+        Span::raw("        "),
+        Span::styled("█".repeat(filled), Style::default().fg(Color::Yellow)),
+        Span::styled(
+            "░".repeat(width - filled),
+            Style::default().fg(Color::Gray).add_modifier(Modifier::DIM),
+        ),
+        Span::raw(format!(" {:.0}%", fill * 100.0)),
+        Span::raw(
+            buffered
+                .map(|time| format!(" ({} ms)", time.as_millis()))
+                .unwrap_or_default(),
+        ),
+    ]
+}
+
+/// One string per row, top to bottom, filled from the bottom up.
+fn generate_vertical_volume_bar(volume: f32, height: u16) -> Vec<String> {
+    let height = height as usize;
+    if height == 0 {
+        return Vec::new();
+    }
+
+    let filled = ((volume.clamp(0.0, 1.0) * height as f32).round() as usize).min(height);
+
+    (0..height)
+        .map(|row| {
+            let from_bottom = height - row - 1;
+            if from_bottom >= filled {
+                " ·".to_string()
+            } else if volume == 0.0 {
+                " ✗".to_string()
+            } else if from_bottom < height * 60 / 100 {
+                " ▓".to_string()
+            } else if from_bottom < height * 80 / 100 {
+                " ▒".to_string()
+            } else {
+                " ░".to_string()
+            }
+        })
+        .collect()
+}